@@ -8,11 +8,16 @@ files in the project carrying such notice may not be copied, modified,
 or distributed except according to those terms.
 */
 extern crate rustc_version;
-use rustc_version::{version_matches};
+use rustc_version::{version_matches, version_meta};
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
 
+    /*
+    Record which compiler we were built with, for `--version`.
+    */
+    println!("cargo:rustc-env=CARGO_SCRIPT_BUILD_RUSTC={}", version_meta().short_version_string);
+
     /*
     Environment might suffer from <https://github.com/DanielKeep/cargo-script/issues/50>.
     */
@@ -0,0 +1,127 @@
+/*
+Copyright ⓒ 2017 cargo-script contributors.
+
+Licensed under the MIT license (see LICENSE or <http://opensource.org
+/licenses/MIT>) or the Apache License, Version 2.0 (see LICENSE of
+<http://www.apache.org/licenses/LICENSE-2.0>), at your option. All
+files in the project carrying such notice may not be copied, modified,
+or distributed except according to those terms.
+*/
+/*!
+This module contains the library interface for running scripts from other programs.
+*/
+use std::path::PathBuf;
+use std::process::Output;
+use error::{Blame, Result};
+use {Args, Ran};
+
+/**
+A script to run.
+*/
+#[derive(Clone, Debug)]
+pub enum ScriptInput {
+    /// A script file, exactly as would be passed on the command line.
+    File(PathBuf),
+    /// A Rust expression, as with `--expr`.
+    Expr(String),
+    /// The source of a script file, as though it had been piped in on standard input.
+    Source(String),
+}
+
+/**
+Options controlling how a script is built and run.
+
+New options may be added in future, so construct this with `..Default::default()`.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Arguments to pass to the script.
+    pub args: Vec<String>,
+    /// Additional dependencies, in the same `name` or `name=version` form as `--dep`.
+    pub deps: Vec<String>,
+    /// Cargo features to enable, as with `--features`.
+    pub features: Option<String>,
+    /// Rust edition to compile with, as with `--edition`.
+    pub edition: Option<String>,
+    /// Environment variables to set for the script when it runs, as with `--env`.
+    pub env: Vec<(String, String)>,
+    /// Build a debug executable, rather than an optimised one?
+    pub debug: bool,
+}
+
+/**
+Builds (or reuses a cached build of) a script, then runs it and returns its captured output.
+
+This uses the same caches as the `cargo-script` command.  Only the script's own output is captured; Cargo's build output still goes to standard error.
+*/
+pub fn run_script(input: ScriptInput, opts: &Options) -> Result<Output> {
+    let args = try!(script_args(input, opts));
+    match try!(::run(args)) {
+        Ran::Output(output) => Ok(output),
+        Ran::Status(code) => Err(format!("script was not run (status {})", code).into()),
+    }
+}
+
+/**
+Builds (or checks there's already an up-to-date build of) a script, without running it, as with `--build-only`.
+
+Follow this with `run_cached_script` to run what was built.
+*/
+pub fn build_script(input: ScriptInput, opts: &Options) -> Result<()> {
+    let mut args = try!(script_args(input, opts));
+    args.build_only = true;
+    match try!(::run(args)) {
+        Ran::Status(0) => Ok(()),
+        Ran::Status(code) => Err(format!("script was not built (status {})", code).into()),
+        Ran::Output(_) => Err("script was run when it should only have been built".into()),
+    }
+}
+
+/**
+Runs the cached build of a script and returns its captured output, as with `--run-cached`.
+
+This never builds anything; if there's no up-to-date build of the script, from `build_script` or an earlier run, it fails instead.
+*/
+pub fn run_cached_script(input: ScriptInput, opts: &Options) -> Result<Output> {
+    let mut args = try!(script_args(input, opts));
+    args.run_cached = true;
+    match try!(::run(args)) {
+        Ran::Output(output) => Ok(output),
+        Ran::Status(code) => Err(format!("script was not run (status {})", code).into()),
+    }
+}
+
+/**
+Turns a script and its options into the arguments `run` expects.
+*/
+fn script_args(input: ScriptInput, opts: &Options) -> Result<Args> {
+    let mut args = Args {
+        args: opts.args.clone(),
+        dep: opts.deps.clone(),
+        features: opts.features.clone(),
+        edition: opts.edition.clone(),
+        env: opts.env.clone(),
+        debug: opts.debug,
+        capture_output: true,
+        cargo_opts: ::CargoOptions::from_env(),
+        ..Default::default()
+    };
+
+    match input {
+        ScriptInput::File(path) => {
+            let path = try!(path.to_str()
+                .ok_or((Blame::Human, "script path is not valid UTF-8")));
+            args.script = Some(path.into());
+        },
+        ScriptInput::Expr(expr) => {
+            args.script = Some(expr);
+            args.expr = true;
+        },
+        ScriptInput::Source(source) => {
+            args.script = Some("-".into());
+            args.script_source = Some(source);
+        },
+    }
+
+    Ok(args)
+}
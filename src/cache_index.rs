@@ -0,0 +1,127 @@
+/*
+Copyright ⓒ 2017 cargo-script contributors.
+
+Licensed under the MIT license (see LICENSE or <http://opensource.org
+/licenses/MIT>) or the Apache License, Version 2.0 (see LICENSE of
+<http://www.apache.org/licenses/LICENSE-2.0>), at your option. All
+files in the project carrying such notice may not be copied, modified,
+or distributed except according to those terms.
+*/
+/*!
+This module maintains an index of the script cache.
+
+Cached packages are named after a hash of their input, which makes them fairly opaque.  The index maps each package back to where it came from, so that cache tooling can say something more useful than a hash.
+*/
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use rustc_serialize::json;
+use error::Result;
+use platform;
+use util;
+
+/**
+Name of the index file, which lives at the top of the cache directory.
+*/
+const INDEX_FILE: &'static str = "cache-index.json";
+
+/**
+What we know about a single cached package.
+*/
+#[derive(Clone, Debug, Eq, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct Entry {
+    /// Path to the script, or a description of the expression.
+    pub source: String,
+
+    /// When the package was first built, in milliseconds since the UNIX epoch.
+    pub created: u64,
+
+    /// `debug` or `release`.
+    pub profile: String,
+
+    /// Path to the compiled executable, if known.
+    pub exe: Option<String>,
+}
+
+/**
+Maps package directory names to what we know about them.
+*/
+pub type Index = BTreeMap<String, Entry>;
+
+/**
+Returns the path to the index file.
+*/
+pub fn get_index_path() -> Result<PathBuf> {
+    let cache_path = try!(platform::get_cache_dir());
+    Ok(cache_path.join(INDEX_FILE))
+}
+
+/**
+Loads the index.
+
+A missing or unreadable index is treated as empty; it only holds information we can do without, and it will fill back in as scripts get built.
+*/
+pub fn load() -> Result<Index> {
+    let index_path = try!(get_index_path());
+    let mut s = String::new();
+    match fs::File::open(&index_path) {
+        Ok(mut f) => { try!(f.read_to_string(&mut s)); },
+        Err(err) => {
+            info!("couldn't open cache index {:?}: {}", index_path, err);
+            return Ok(Index::new());
+        }
+    }
+    match json::decode(&s) {
+        Ok(index) => Ok(index),
+        Err(err) => {
+            info!("ignoring damaged cache index {:?}: {}", index_path, err);
+            Ok(Index::new())
+        }
+    }
+}
+
+/**
+Records a freshly built package in the index.
+
+If the package was already indexed, its creation time is kept.  While we're here, entries for packages which no longer exist in `script_cache` are dropped.
+*/
+pub fn record(script_cache: &Path, name: &str, mut entry: Entry) -> Result<()> {
+    let mut index = try!(load());
+
+    if let Some(old) = index.get(name) {
+        entry.created = old.created;
+    }
+    index.insert(name.into(), entry);
+
+    let stale: Vec<String> = index.keys()
+        .filter(|k| !script_cache.join(k).is_dir())
+        .cloned()
+        .collect();
+    for k in stale {
+        info!("dropping {:?} from cache index", k);
+        index.remove(&k);
+    }
+
+    save(&index)
+}
+
+/**
+Writes the index out.
+
+This is done atomically, so a partially written index is never left behind.  If two builds finish at the same time, one of them can lose its entry; that's fine.
+*/
+fn save(index: &Index) -> Result<()> {
+    let index_path = try!(get_index_path());
+    let index_str = try!(json::encode(index).map_err(|err| err.to_string()));
+    try!(util::write_atomic(&index_path, |f| f.write_all(index_str.as_bytes())));
+    Ok(())
+}
+
+/**
+Finds the index entry for a compiled executable.
+*/
+pub fn find_exe<'a>(index: &'a Index, exe_path: &Path) -> Option<&'a Entry> {
+    index.values()
+        .find(|e| e.exe.as_ref().map(|p| Path::new(p) == exe_path).unwrap_or(false))
+}
@@ -0,0 +1,291 @@
+/*
+Copyright ⓒ 2017 cargo-script contributors.
+
+Licensed under the MIT license (see LICENSE or <http://opensource.org
+/licenses/MIT>) or the Apache License, Version 2.0 (see LICENSE of
+<http://www.apache.org/licenses/LICENSE-2.0>), at your option. All
+files in the project carrying such notice may not be copied, modified,
+or distributed except according to those terms.
+*/
+/*!
+This module contains code for configuration files.
+
+There are two kinds: a global `config.toml` in the config directory, and project-local `.cargo-script.toml` files.  Settings in a project file take precedence over the global file, and anything given on the command line takes precedence over both.
+*/
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use toml;
+use error::{Blame, Result};
+use platform;
+
+/**
+Name of the project-local configuration file we look for.
+*/
+pub const CONFIG_FILE: &'static str = ".cargo-script.toml";
+
+/**
+Name of the global configuration file, in the config directory.
+*/
+pub const GLOBAL_CONFIG_FILE: &'static str = "config.toml";
+
+/**
+Defaults read from configuration files.
+*/
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Config {
+    /// Rust edition to compile with, as with `--edition`.
+    pub edition: Option<String>,
+
+    /// Build debug executables, as with `--debug`?
+    pub debug: Option<bool>,
+
+    /// Additional dependencies, in the same form as `--dep`.
+    pub dependencies: Vec<String>,
+
+    /// Additional dependencies for expressions and loops only, in the same form as `--dep`.
+    pub expr_dependencies: Vec<String>,
+
+    /// Template to use for expressions, as with `--template`.
+    pub template: Option<String>,
+
+    /// Don't touch the network, as with `--offline`?
+    pub offline: Option<bool>,
+}
+
+impl Config {
+    /**
+    Layers `over` on top of this configuration, such that anything set in `over` wins.
+    */
+    pub fn merge(self, over: Config) -> Config {
+        Config {
+            edition: over.edition.or(self.edition),
+            debug: over.debug.or(self.debug),
+            dependencies: merge_deps(self.dependencies, over.dependencies),
+            expr_dependencies: merge_deps(self.expr_dependencies, over.expr_dependencies),
+            template: over.template.or(self.template),
+            offline: over.offline.or(self.offline),
+        }
+    }
+}
+
+/**
+Combines two lists of `--dep` specs, dropping anything in `base` for a package which `over` also names.
+*/
+pub fn merge_deps(base: Vec<String>, over: Vec<String>) -> Vec<String> {
+    fn dep_name(spec: &str) -> &str {
+        spec.splitn(2, '=').next().unwrap()
+    }
+
+    let mut deps: Vec<String> = base.into_iter()
+        .filter(|b| !over.iter().any(|o| dep_name(o) == dep_name(b)))
+        .collect();
+    deps.extend(over);
+    deps
+}
+
+#[test]
+fn test_merge() {
+    let global = Config {
+        edition: Some("2021".into()),
+        debug: Some(true),
+        dependencies: vec!["regex".into(), "time=0.1.25".into()],
+        template: Some("grabbag".into()),
+        ..Config::default()
+    };
+    let project = Config {
+        edition: Some("2018".into()),
+        dependencies: vec!["time=0.1.36".into()],
+        offline: Some(false),
+        ..Config::default()
+    };
+    assert_eq!(global.merge(project), Config {
+        edition: Some("2018".into()),
+        debug: Some(true),
+        dependencies: vec!["regex".into(), "time=0.1.36".into()],
+        expr_dependencies: vec![],
+        template: Some("grabbag".into()),
+        offline: Some(false),
+    });
+}
+
+/**
+Loads the configuration that applies to a script in `dir`: the global configuration, overridden by the nearest project configuration.
+*/
+pub fn load(dir: &Path) -> Result<Config> {
+    let global = match platform::get_config_dir() {
+        Ok(config_dir) => try!(load_file(&config_dir.join(GLOBAL_CONFIG_FILE))),
+        Err(err) => {
+            info!("couldn't get config dir; skipping global config: {}", err);
+            None
+        }
+    };
+
+    let project = match try!(find(dir)) {
+        Some((_, config)) => Some(config),
+        None => None,
+    };
+
+    Ok(global.unwrap_or_else(Config::default).merge(project.unwrap_or_else(Config::default)))
+}
+
+/**
+Loads a configuration file, if it exists.
+*/
+fn load_file(path: &Path) -> Result<Option<Config>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    info!("loading config from {:?}", path);
+    let mut s = String::new();
+    try!(try!(fs::File::open(path)).read_to_string(&mut s));
+    Ok(Some(try!(parse(&s, path))))
+}
+
+/**
+Looks for a configuration file in `dir`, then each of its parents in turn, and loads the first one found.
+
+Returns the path to the file along with its contents.
+*/
+pub fn find(dir: &Path) -> Result<Option<(PathBuf, Config)>> {
+    let mut dir = Some(dir);
+    while let Some(d) = dir {
+        let path = d.join(CONFIG_FILE);
+        if let Some(config) = try!(load_file(&path)) {
+            return Ok(Some((path, config)));
+        }
+        dir = d.parent();
+    }
+    Ok(None)
+}
+
+/**
+Parses the contents of a configuration file.  `path` is used for error messages, and to resolve relative `path:` dependencies.
+*/
+fn parse(s: &str, path: &Path) -> Result<Config> {
+    let mut parser = toml::Parser::new(s);
+    let table = match parser.parse() {
+        Some(table) => table,
+        None => {
+            let msg = match parser.errors.first() {
+                Some(err) => {
+                    let (line, col) = parser.to_linecol(err.lo);
+                    format!("could not parse {}: {} (line {}, column {})",
+                        path.display(), err.desc, line + 1, col + 1)
+                },
+                None => format!("could not parse {}", path.display()),
+            };
+            return Err((Blame::Human, msg).into());
+        }
+    };
+
+    let bad = |key: &str, expected: &str| -> Result<Config> {
+        Err((Blame::Human, format!("invalid `{}` in {}: expected {}", key, path.display(), expected)).into())
+    };
+
+    let mut config = Config::default();
+    for (key, value) in table {
+        match (&*key, value) {
+            ("edition", toml::Value::String(edition)) => {
+                match &*edition {
+                    "2015" | "2018" | "2021" => config.edition = Some(edition),
+                    _ => return bad(&key, "one of \"2015\", \"2018\", or \"2021\""),
+                }
+            },
+            ("debug", toml::Value::Boolean(debug)) => config.debug = Some(debug),
+            ("dependencies", toml::Value::Array(deps)) | ("expr-dependencies", toml::Value::Array(deps)) => {
+                let mut specs = vec![];
+                for dep in deps {
+                    match dep {
+                        toml::Value::String(dep) => specs.push(rebase_dep_path(dep, path)),
+                        _ => return bad(&key, "an array of strings"),
+                    }
+                }
+                if key == "dependencies" {
+                    config.dependencies = specs;
+                } else {
+                    config.expr_dependencies = specs;
+                }
+            },
+            ("template", toml::Value::String(template)) => config.template = Some(template),
+            ("offline", toml::Value::Boolean(offline)) => config.offline = Some(offline),
+
+            ("edition", _) | ("template", _) => return bad(&key, "a string"),
+            ("dependencies", _) | ("expr-dependencies", _) => return bad(&key, "an array of strings"),
+            ("debug", _) | ("offline", _) => return bad(&key, "true or false"),
+            _ => return Err((Blame::Human, format!("unknown key `{}` in {}", key, path.display())).into()),
+        }
+    }
+
+    Ok(config)
+}
+
+/**
+Makes a relative `path:` dependency relative to the configuration file at `config_path`, rather than to wherever `cargo-script` is run from.  Otherwise, a committed config file would only work from its own directory.  Anything else is left alone.
+*/
+fn rebase_dep_path(spec: String, config_path: &Path) -> String {
+    let rebased = {
+        let mut parts = spec.splitn(2, '=');
+        let name = parts.next().unwrap();
+        match parts.next() {
+            Some(rest) if rest.starts_with("path:") => {
+                let dir = &rest["path:".len()..];
+                // Leave `~` for `expand_tilde`, and empty paths for `expand_dep_spec` to complain about.
+                if dir == "" || dir.starts_with('~') || Path::new(dir).is_absolute() {
+                    None
+                } else {
+                    let base = config_path.parent().unwrap_or(Path::new(""));
+                    Some(format!("{}=path:{}", name, base.join(dir).to_string_lossy()))
+                }
+            },
+            _ => None,
+        }
+    };
+    rebased.unwrap_or(spec)
+}
+
+#[test]
+fn test_rebase_dep_path() {
+    let config_path = Path::new("/proj").join(CONFIG_FILE);
+    let rebase = |spec: &str| rebase_dep_path(spec.into(), &config_path);
+    assert_eq!(rebase("lib=path:../lib"), format!("lib=path:{}", Path::new("/proj").join("../lib").to_string_lossy()));
+    assert_eq!(rebase("lib=path:lib"), format!("lib=path:{}", Path::new("/proj").join("lib").to_string_lossy()));
+    assert_eq!(rebase("lib=path:~/lib"), "lib=path:~/lib");
+    assert_eq!(rebase("lib=path:"), "lib=path:");
+    assert_eq!(rebase("regex"), "regex");
+    assert_eq!(rebase("time=0.1.25"), "time=0.1.25");
+    assert_eq!(rebase("foo=git:https://example.com/foo.git"), "foo=git:https://example.com/foo.git");
+
+    let config = parse("dependencies = [\"lib=path:lib\"]", &config_path).unwrap();
+    assert_eq!(config.dependencies, vec![format!("lib=path:{}", Path::new("/proj").join("lib").to_string_lossy())]);
+}
+
+#[test]
+fn test_parse() {
+    let path = Path::new(CONFIG_FILE);
+
+    assert_eq!(parse("", path).ok(), Some(Config::default()));
+    assert_eq!(parse(r#"
+        edition = "2018"
+        debug = true
+        dependencies = ["regex", "time=0.1.25"]
+        expr-dependencies = ["itertools"]
+        template = "grabbag"
+        offline = true
+    "#, path).ok(), Some(Config {
+        edition: Some("2018".into()),
+        debug: Some(true),
+        dependencies: vec!["regex".into(), "time=0.1.25".into()],
+        expr_dependencies: vec!["itertools".into()],
+        template: Some("grabbag".into()),
+        offline: Some(true),
+    }));
+
+    let err = |s| parse(s, path).unwrap_err().to_string();
+    assert_eq!(err("edition = 2018"), "invalid `edition` in .cargo-script.toml: expected a string");
+    assert_eq!(err("edition = \"2020\""), "invalid `edition` in .cargo-script.toml: expected one of \"2015\", \"2018\", or \"2021\"");
+    assert_eq!(err("dependencies = [1]"), "invalid `dependencies` in .cargo-script.toml: expected an array of strings");
+    assert_eq!(err("offline = \"yes\""), "invalid `offline` in .cargo-script.toml: expected true or false");
+    assert_eq!(err("editon = \"2018\""), "unknown key `editon` in .cargo-script.toml");
+    assert!(err("edition = ").contains("(line 1, column "));
+}
@@ -33,6 +33,18 @@ pub const SCRIPT_BODY_SUB: &'static str = "script";
 /// Substitution for the script prelude.
 pub const SCRIPT_PRELUDE_SUB: &'static str = "prelude";
 
+/// Substitution for statements that bind variables, such as `input`, before the script runs.
+pub const SCRIPT_BINDINGS_SUB: &'static str = "bindings";
+
+/// Substitution for the statement that prints the result of an expression.
+pub const SCRIPT_OUTPUT_SUB: &'static str = "output";
+
+/// How the result of an expression is printed, unless `--output-format` says otherwise.
+pub const DEFAULT_OUTPUT: &'static str = r#"println!("{:?}", __cargo_script_expr)"#;
+
+/// How the result of an expression is used with `--exit-expr`: as the exit code, which means it has to be an `i32`.
+pub const EXIT_OUTPUT: &'static str = r#"::std::process::exit(__cargo_script_expr)"#;
+
 /// The template used for script file inputs.
 pub const FILE_TEMPLATE: &'static str = r#"#{script}"#;
 
@@ -53,9 +65,10 @@ fn main() {
     }
 }
 
-fn try_main() -> Result<(), Box<std::error::Error>> {
+fn try_main() -> Result<(), Box<dyn std::error::Error>> {
+#{bindings}
     match {#{script}} {
-        __cargo_script_expr => println!("{:?}", __cargo_script_expr)
+        __cargo_script_expr => #{output}
     }
     Ok(())
 }
@@ -75,6 +88,7 @@ use std::any::Any;
 use std::io::prelude::*;
 
 fn main() {
+#{bindings}
     let mut closure = enforce_closure(
 {#{script}}
     );
@@ -105,11 +119,12 @@ where F: FnMut(&str) -> T, T: 'static {
 
 /// The template used for `--count --loop` input.
 pub const LOOP_COUNT_TEMPLATE: &'static str = r#"
-%p
+#{prelude}
 use std::any::Any;
 use std::io::prelude::*;
 
 fn main() {
+#{bindings}
     let mut closure = enforce_closure(
 {#{script}}
     );
@@ -140,6 +155,53 @@ where F: FnMut(&str, usize) -> T, T: 'static {
 }
 "#;
 
+/**
+Binds the contents of the `--input` file to `input`.
+
+The path is passed in when the script is run, rather than baked in, so the same executable works for any file.
+*/
+pub const INPUT_BINDING: &'static str = r#"
+    let input: String = {
+        let path = ::std::env::var_os("CARGO_SCRIPT_INPUT").unwrap_or_default();
+        match ::std::fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("error: could not read {:?}: {}", path, e);
+                ::std::process::exit(1)
+            }
+        }
+    };
+"#;
+
+/**
+Binds everything on standard input to the variable named by `--stdin-as`, which is substituted for `#{name}`.
+*/
+pub const STDIN_BINDING: &'static str = r#"
+    let #{name}: String = {
+        let mut s = String::new();
+        if let Err(e) = ::std::io::Read::read_to_string(&mut ::std::io::stdin(), &mut s) {
+            eprintln!("error: could not read standard input: {}", e);
+            ::std::process::exit(1)
+        }
+        s
+    };
+"#;
+
+/**
+What gets built in place of the script by `--prime`, so that only the dependencies take any time.
+*/
+pub const PRIME_STUB: &'static str = "fn main() {}\n";
+
+/**
+Line written to standard output just before the script runs with `--output-marker`, so that its output can be picked out of anything else written there.
+*/
+pub const OUTPUT_BEGIN_MARKER: &'static str = "--cargo-script-output-begin--";
+
+/**
+Line written to standard output once the script has finished with `--output-marker`; see `OUTPUT_BEGIN_MARKER`.
+*/
+pub const OUTPUT_END_MARKER: &'static str = "--cargo-script-output-end--";
+
 /// Substitution for the identifier-safe name of the script.
 pub const MANI_NAME_SUB: &'static str = "name";
 
@@ -160,6 +222,11 @@ name = "#{name}"
 path = "#{file}.rs"
 "##;
 
+/**
+The name of the file in the config directory whose contents go in the prelude of every expression and loop script, unless `--prelude-file` names another.
+*/
+pub const PRELUDE_FILE: &'static str = "prelude.rs";
+
 /**
 The name of the package metadata file.
 */
@@ -0,0 +1,154 @@
+/*
+Copyright ⓒ 2017 cargo-script contributors.
+
+Licensed under the MIT license (see LICENSE or <http://opensource.org
+/licenses/MIT>) or the Apache License, Version 2.0 (see LICENSE of
+<http://www.apache.org/licenses/LICENSE-2.0>), at your option. All
+files in the project carrying such notice may not be copied, modified,
+or distributed except according to those terms.
+*/
+/*!
+This module contains code for checking that the environment is set up so that scripts can actually be built.
+*/
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use clap;
+use error::{MainError, Result};
+use platform;
+use util;
+
+#[derive(Debug)]
+pub struct Args {
+    /// What `--color` (or `CARGO_SCRIPT_COLOR`) was set to, if anything.
+    color: Option<String>,
+}
+
+/**
+The subcommand's name, and the aliases it also answers to.
+*/
+pub const NAMES: &'static [&'static str] = &["doctor", "check-env"];
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::SubCommand;
+
+        SubCommand::with_name(NAMES[0])
+            .about("Check that cargo-script has everything it needs to build and run scripts.")
+            .aliases(&NAMES[1..])
+    }
+
+    pub fn parse(_: &clap::ArgMatches, color: Option<String>) -> Self {
+        Args {
+            color: color,
+        }
+    }
+}
+
+/**
+How a single check turned out.
+*/
+enum Outcome {
+    /// All is well.
+    Pass(String),
+    /// Something looks off, but scripts should still work.
+    Warn(String),
+    /// Scripts won't work until this is fixed.
+    Fail(String),
+    /// Not really a check; just something worth knowing.
+    Info(String),
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    let checks: Vec<(&str, Outcome)> = vec![
+        ("cargo", check_cargo()),
+        ("rustc", check_rustc()),
+        ("cache dir", check_cache_dir()),
+        ("config dir", check_config_dir()),
+        ("colour", check_color(args.color.as_ref().map(|s| &**s))),
+    ];
+
+    let mut failed = false;
+    for (what, outcome) in checks {
+        let (tag, msg) = match outcome {
+            Outcome::Pass(msg) => ("  ok", msg),
+            Outcome::Warn(msg) => ("warn", msg),
+            Outcome::Fail(msg) => { failed = true; ("FAIL", msg) },
+            Outcome::Info(msg) => ("info", msg),
+        };
+        println!("[{}] {}: {}", tag, what, msg);
+    }
+
+    if failed {
+        println!("Scripts cannot be built until the failures above are fixed.");
+        Ok(1)
+    } else {
+        println!("Everything needed to build scripts looks fine.");
+        Ok(0)
+    }
+}
+
+fn check_cargo() -> Outcome {
+    match ::cargo_version(None) {
+        Ok(version) => Outcome::Pass(format!("cargo {}", version)),
+        Err(err) => Outcome::Fail(format!("could not run `cargo -V`; is it on your PATH? ({})", err)),
+    }
+}
+
+fn check_rustc() -> Outcome {
+    match ::rustc_version(None) {
+        Some(version) => Outcome::Pass(version),
+        None => Outcome::Fail("could not run `rustc -V`; is it on your PATH, or is $RUSTC wrong?".into()),
+    }
+}
+
+fn check_cache_dir() -> Outcome {
+    let dir = match platform::get_cache_dir() {
+        Ok(dir) => dir,
+        Err(err) => return Outcome::Fail(err.to_string()),
+    };
+
+    // We'd create it on the first build anyway, so we may as well find out now whether that works.
+    if let Err(err) = fs::create_dir_all(&dir) {
+        return Outcome::Fail(format!("{} could not be created: {}", dir.display(), err));
+    }
+
+    match check_writable(&dir) {
+        Ok(()) => Outcome::Pass(format!("{} (writable)", dir.display())),
+        Err(err) => Outcome::Fail(format!("{} is not writable: {}", dir.display(), err)),
+    }
+}
+
+fn check_config_dir() -> Outcome {
+    let dir = match platform::get_config_dir() {
+        Ok(dir) => dir,
+        Err(err) => return Outcome::Warn(format!("{}; templates and config.toml won't be available", err)),
+    };
+
+    if !dir.exists() {
+        return Outcome::Pass(format!("{} (doesn't exist yet; that's fine until you add templates or a config.toml)", dir.display()));
+    }
+
+    match check_writable(&dir) {
+        Ok(()) => Outcome::Pass(format!("{} (writable)", dir.display())),
+        Err(err) => Outcome::Warn(format!("{} is not writable, so templates can't be added: {}", dir.display(), err)),
+    }
+}
+
+fn check_color(color: Option<&str>) -> Outcome {
+    match platform::cargo_color(color) {
+        Ok(Some(color)) => Outcome::Info(format!("cargo is run with `--color {}`", color)),
+        Ok(None) => Outcome::Info("left for cargo to decide".into()),
+        Err(err) => Outcome::Warn(err.to_string()),
+    }
+}
+
+/**
+Checks that we can write to a directory by actually doing it.
+*/
+fn check_writable(dir: &Path) -> ::std::result::Result<(), MainError> {
+    let probe = dir.join(".cargo-script-doctor");
+    try!(util::write_atomic(&probe, |f| f.write_all(b"")));
+    try!(fs::remove_file(&probe));
+    Ok(())
+}
@@ -22,12 +22,28 @@ Shorthand for the program's common result type.
 */
 pub type Result<T> = StdResult<T, MainError>;
 
+/**
+Exit code used when the script fails to build.
+
+This is *not* what `cargo run` uses (101), since that's also what a Rust program exits with when it panics, and a script that panics has to be distinguishable from one that never ran.
+*/
+pub const BUILD_FAILED_EXIT_CODE: i32 = 102;
+
+/**
+Exit code used when the script is killed for running past its `--timeout`.  This matches what the `timeout` utility does.
+*/
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
 /**
 Represents an error in the program.
 */
 #[derive(Debug)]
 pub enum MainError {
     Io(Blame, io::Error),
+    /// Cargo failed to build the script, exiting with the given status, if it had one.
+    BuildFailed(Option<i32>),
+    /// The script ran for longer than the given number of seconds, and was killed.
+    TimedOut(u64),
     Tag(Blame, Cow<'static, str>, Box<MainError>),
     Other(Blame, Box<Error>),
     OtherOwned(Blame, String),
@@ -50,7 +66,21 @@ impl MainError {
             | Tag(blame, _, _)
             | Other(blame, _)
             | OtherOwned(blame, _)
-            | OtherBorrowed(blame, _) => blame
+            | OtherBorrowed(blame, _) => blame,
+            BuildFailed(_) | TimedOut(_) => Blame::Human,
+        }
+    }
+
+    /**
+    The exit code the program should use for this error.
+    */
+    pub fn exit_code(&self) -> i32 {
+        use self::MainError::*;
+        match *self {
+            BuildFailed(_) => BUILD_FAILED_EXIT_CODE,
+            TimedOut(_) => TIMEOUT_EXIT_CODE,
+            Tag(_, _, ref err) => err.exit_code(),
+            _ => 1,
         }
     }
 
@@ -67,6 +97,7 @@ impl MainError {
             | OtherOwned(ref mut cur_blame, _)
             | OtherBorrowed(ref mut cur_blame, _)
             => *cur_blame = blame,
+            BuildFailed(_) | TimedOut(_) => (),
         }
     }
 }
@@ -77,6 +108,9 @@ impl fmt::Display for MainError {
         use std::fmt::Display;
         match *self {
             Io(_, ref err) => Display::fmt(err, fmt),
+            BuildFailed(Some(st)) => write!(fmt, "could not build script: cargo exited with status {}", st),
+            BuildFailed(None) => write!(fmt, "could not build script: cargo exited abnormally"),
+            TimedOut(secs) => write!(fmt, "script timed out after {} second{}", secs, if secs == 1 { "" } else { "s" }),
             Tag(_, ref msg, ref err) => write!(fmt, "{}: {}", msg, err),
             Other(_, ref err) => Display::fmt(err, fmt),
             OtherOwned(_, ref err) => Display::fmt(err, fmt),
@@ -90,6 +124,8 @@ impl Error for MainError {
         use self::MainError::*;
         match *self {
             Io(_, ref err) => err.description(),
+            BuildFailed(_) => "could not build script",
+            TimedOut(_) => "script timed out",
             Tag(_, ref msg, _) => msg,
             Other(_, ref err) => err.description(),
             OtherOwned(_, ref err) => err,
@@ -27,11 +27,16 @@ pub enum Args {
     Uninstall,
 }
 
+/**
+Everything the subcommand can be called; just its name, since it has no aliases.
+*/
+pub const NAMES: &'static [&'static str] = &["file-association"];
+
 impl Args {
     pub fn subcommand() -> clap::App<'static, 'static> {
         use clap::{AppSettings, Arg, SubCommand};
 
-        SubCommand::with_name("file-association")
+        SubCommand::with_name(NAMES[0])
             .about("Manage file assocations.")
             .setting(AppSettings::SubcommandRequiredElseHelp)
             .subcommand(SubCommand::with_name("install")
@@ -0,0 +1,200 @@
+/*
+Copyright ⓒ 2017 cargo-script contributors.
+
+Licensed under the MIT license (see LICENSE or <http://opensource.org
+/licenses/MIT>) or the Apache License, Version 2.0 (see LICENSE of
+<http://www.apache.org/licenses/LICENSE-2.0>), at your option. All
+files in the project carrying such notice may not be copied, modified,
+or distributed except according to those terms.
+*/
+/*!
+This module contains code for expiring old entries from the binary cache.
+*/
+use std::fs;
+use std::io;
+use std::path::Path;
+use clap;
+use cache_index;
+use error::{Blame, Result};
+use platform;
+use util;
+use RemoveKind;
+
+/**
+How old something in the binary cache has to be before `gc` removes it, if the user doesn't say otherwise.
+*/
+const DEFAULT_OLDER_THAN: &'static str = "30d";
+
+#[derive(Debug)]
+pub struct Args {
+    /// Maximum age, in milliseconds.
+    older_than: u64,
+    kind: RemoveKind,
+}
+
+/**
+Everything the subcommand can be called; just its name, since it has no aliases.
+*/
+pub const NAMES: &'static [&'static str] = &["gc"];
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::{Arg, SubCommand};
+
+        SubCommand::with_name(NAMES[0])
+            .about("Remove old entries from the binary cache.")
+
+            .arg(Arg::with_name("older_than")
+                .help("Remove entries which haven't been modified within this long.  Accepts a number followed by one of `s`, `m`, `h`, `d`, or `w`; for example: `12h`.")
+                .long("older-than")
+                .takes_value(true)
+                .default_value(DEFAULT_OLDER_THAN)
+                .validator(|s| parse_duration(&s).map(|_| ()).map_err(|e| e.to_string()))
+            )
+            .arg(Arg::with_name("dry_run")
+                .help("Report what would be removed without actually removing anything.")
+                .long("dry-run")
+            )
+    }
+
+    pub fn parse(m: &clap::ArgMatches) -> Self {
+        Args {
+            older_than: parse_duration(m.value_of("older_than").unwrap()).unwrap(),
+            kind: match m.is_present("dry_run") {
+                true => RemoveKind::DryRun,
+                false => RemoveKind::ForReal,
+            },
+        }
+    }
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    info!("collecting binary cache garbage ({:?})", args);
+
+    let cache_dir = try!(::get_binary_cache_path());
+    let cutoff = platform::current_time().saturating_sub(args.older_than);
+    info!("cutoff: {:>20?} ms", cutoff);
+
+    // Used to say which scripts are affected, rather than just counting files.
+    let index = try!(cache_index::load());
+
+    let mut entries = 0;
+    let mut bytes = 0;
+    if cache_dir.is_dir() {
+        try!(collect(&cache_dir, cutoff, args.kind, &index, &mut entries, &mut bytes));
+    } else {
+        info!("{:?} does not exist; skipping", cache_dir);
+    }
+
+    if args.kind.for_real() {
+        println!("cargo script gc: removed {} entries, freeing {}.",
+            entries, util::human_size(bytes));
+    } else {
+        println!("cargo script gc: would remove {} entries, {}.",
+            entries, util::human_size(bytes));
+    }
+
+    Ok(0)
+}
+
+/**
+Parses a duration like `90m` or `7d` into milliseconds.
+*/
+fn parse_duration(s: &str) -> Result<u64> {
+    let bad = || (Blame::Human, format!("invalid duration `{}`: expected a number followed by one of `s`, `m`, `h`, `d`, or `w`", s)).into();
+
+    let split = s.find(|c: char| !c.is_digit(10)).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+
+    let num: u64 = try!(num.parse().map_err(|_| bad()));
+    let unit_ms = match unit {
+        "s" => 1000,
+        "m" => 60*1000,
+        "h" => 60*60*1000,
+        "d" => 24*60*60*1000,
+        "w" => 7*24*60*60*1000,
+        _ => return Err(bad())
+    };
+
+    num.checked_mul(unit_ms).ok_or_else(bad)
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("0s").ok(), Some(0));
+    assert_eq!(parse_duration("90m").ok(), Some(90*60*1000));
+    assert_eq!(parse_duration("12h").ok(), Some(12*60*60*1000));
+    assert_eq!(parse_duration("7d").ok(), Some(7*24*60*60*1000));
+    assert_eq!(parse_duration("2w").ok(), Some(14*24*60*60*1000));
+
+    assert!(parse_duration("").is_err());
+    assert!(parse_duration("7").is_err());
+    assert!(parse_duration("d").is_err());
+    assert!(parse_duration("-7d").is_err());
+    assert!(parse_duration("7 d").is_err());
+    assert!(parse_duration("7y").is_err());
+    assert!(parse_duration("99999999999999999999d").is_err());
+}
+
+/**
+Removes every file under `dir` which was last modified at or before `cutoff`, along with any directories that end up empty as a result.  Executables that appear in `index` are listed as they go.
+
+`dir` itself is never removed.
+*/
+fn collect(dir: &Path, cutoff: u64, kind: RemoveKind, index: &cache_index::Index, entries: &mut usize, bytes: &mut u64) -> Result<()> {
+    for child in try!(fs::read_dir(dir)) {
+        let child = try!(child);
+        let path = child.path();
+
+        if try!(child.file_type()).is_dir() {
+            try!(collect(&path, cutoff, kind, index, entries, bytes));
+
+            // Don't leave husks lying around.
+            if kind.for_real() && ::ALLOW_AUTO_REMOVE && try!(is_empty_dir(&path)) {
+                info!("removing empty {:?}", path);
+                if let Err(err) = fs::remove_dir(&path) {
+                    error!("failed to remove {:?} from cache: {}", path, err);
+                }
+            }
+            continue;
+        }
+
+        let mtime = match fs::File::open(&path) {
+            Ok(file) => platform::file_last_modified(&file),
+            Err(err) => {
+                info!("couldn't open {:?}: {}", path, err);
+                continue;
+            }
+        };
+        if mtime > cutoff {
+            continue;
+        }
+
+        *entries += 1;
+        *bytes += util::disk_usage(&path).unwrap_or(0);
+
+        if let Some(entry) = cache_index::find_exe(index, &path) {
+            println!("- {} ({})", entry.source, entry.profile);
+        }
+
+        if !kind.for_real() {
+            info!("would remove {:?}", path);
+            continue;
+        }
+
+        info!("removing {:?}", path);
+        if ::ALLOW_AUTO_REMOVE {
+            if let Err(err) = fs::remove_file(&path) {
+                error!("failed to remove {:?} from cache: {}", path, err);
+            }
+        } else {
+            info!("(suppressed remove)");
+        }
+    }
+
+    Ok(())
+}
+
+fn is_empty_dir(path: &Path) -> io::Result<bool> {
+    Ok(try!(fs::read_dir(path)).next().is_none())
+}
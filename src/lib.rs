@@ -0,0 +1,4322 @@
+/*
+Copyright ⓒ 2015-2017 cargo-script contributors.
+
+Licensed under the MIT license (see LICENSE or <http://opensource.org
+/licenses/MIT>) or the Apache License, Version 2.0 (see LICENSE of
+<http://www.apache.org/licenses/LICENSE-2.0>), at your option. All
+files in the project carrying such notice may not be copied, modified,
+or distributed except according to those terms.
+*/
+/*!
+`cargo-script` is a Cargo subcommand designed to let people quickly and easily run Rust "scripts" which can make use of Cargo's package ecosystem.
+
+Or, to put it in other words, it lets you write useful, but small, Rust programs without having to create a new directory and faff about with `Cargo.toml`.
+
+As such, `cargo-script` does two major things:
+
+1. Given a script, it extracts the embedded Cargo manifest and merges it with some sensible defaults.  This manifest, along with the source code, is written to a fresh Cargo package on-disk.
+
+2. It caches the generated and compiled packages, regenerating them only if the script or its metadata have changed.
+
+Most of this is also available as a library, via `run_script`, for anything that wants to run scripts without shelling out to `cargo-script` itself.
+*/
+extern crate clap;
+extern crate env_logger;
+#[macro_use] extern crate lazy_static;
+#[macro_use] extern crate log;
+extern crate open;
+extern crate regex;
+extern crate rustc_serialize;
+extern crate semver;
+extern crate shaman;
+extern crate toml;
+
+#[cfg(feature="chan")]
+#[macro_use] extern crate chan;
+
+/**
+If this is set to `true`, the digests used for package IDs will be replaced with "stub" to make testing a bit easier.  Obviously, you don't want this `true` for release...
+*/
+const STUB_HASHES: bool = false;
+
+/**
+If this is set to `false`, then code that automatically deletes stuff *won't*.
+*/
+const ALLOW_AUTO_REMOVE: bool = true;
+
+/**
+Length of time to suppress Cargo output.
+*/
+#[cfg(feature="suppress-cargo-output")]
+const CARGO_OUTPUT_TIMEOUT: u64 = 2_000/*ms*/;
+
+// This macro exists for 1.11 support.
+#[cfg(windows)]
+macro_rules! if_windows {
+    (@as_expr $e:expr) => { $e };
+    ($($tts:tt)*) => { if_windows! { @as_expr { $($tts)* } } };
+}
+
+#[cfg(not(windows))]
+macro_rules! if_windows {
+    ($($tts:tt)*) => { {} };
+}
+
+mod api;
+mod cache_index;
+mod config;
+mod consts;
+mod doctor;
+mod error;
+mod gc;
+mod manifest;
+mod migrate;
+mod platform;
+mod templates;
+mod util;
+
+#[cfg(windows)]
+mod file_assoc;
+
+#[cfg(not(windows))]
+mod file_assoc {}
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use semver::Version;
+
+use error::ResultExt;
+use platform::MigrationKind;
+use util::{ChainMap, Defer, PathExt};
+
+pub use api::{build_script, run_cached_script, run_script, Options, ScriptInput};
+pub use error::{Blame, MainError, Result, BUILD_FAILED_EXIT_CODE, TIMEOUT_EXIT_CODE};
+pub use platform::{get_cache_dir, get_config_dir};
+pub use templates::expand as expand_template;
+
+#[derive(Debug)]
+enum SubCommand {
+    Script(Args),
+    Doctor(doctor::Args),
+    Gc(gc::Args),
+    Migrate(migrate::Args),
+    Templates(templates::Args),
+    #[cfg(windows)]
+    FileAssoc(file_assoc::Args),
+}
+
+#[derive(Clone, Debug, Default)]
+struct Args {
+    script: Option<String>,
+    args: Vec<String>,
+    features: Option<String>,
+    all_features: bool,
+    no_default_features: bool,
+    edition: Option<String>,
+    default_edition: Option<String>,
+    env: Vec<(String, String)>,
+    backtrace: Option<String>,
+    rustc_flags: Vec<String>,
+    target: Option<String>,
+    toolchain: Option<String>,
+
+    expr: bool,
+    file: bool,
+    loop_: bool,
+    count: bool,
+
+    pkg_path: Option<String>,
+    gen_pkg_only: bool,
+    build_only: bool,
+    run_cached: bool,
+    bin: Option<String>,
+    crate_type: Option<String>,
+    clear_cache: Option<RemoveKind>,
+    debug: bool,
+    release: bool,
+    profile: Option<String>,
+    debug_symbols: bool,
+    dep: Vec<String>,
+    dep_extern: Vec<String>,
+    dep_features: Vec<String>,
+    dep_no_default: Vec<String>,
+    dump_expanded: bool,
+    explain_cache_decision: bool,
+    expr_prelude: Vec<String>,
+    prelude_file: Option<String>,
+    extern_: Vec<String>,
+    extern_glob: Vec<String>,
+    input: Option<String>,
+    interactive: bool,
+    stdin_as: Option<String>,
+    output_format: OutputFormat,
+    output_marker: bool,
+    exit_expr: bool,
+    extract: Option<String>,
+    force: bool,
+    format: bool,
+    install: bool,
+    install_dir: Option<String>,
+    install_name: Option<String>,
+    install_overwrite: bool,
+    json_messages: bool,
+    keep_temp: bool,
+    list_cache: Option<CacheSort>,
+    manifest: Vec<String>,
+    name: Option<String>,
+    post_run_hook: Option<String>,
+    pre_build_hook: Option<String>,
+    prime: bool,
+    print_binary_path: bool,
+    run_dir: Option<String>,
+    strip: bool,
+    timeout: Option<u64>,
+    unstable_features: Vec<String>,
+    update: bool,
+    use_bincache: Option<bool>,
+    warn_dirty_deps: bool,
+    migrate_data: Option<MigrationKind>,
+    build_kind: BuildKind,
+    template: Option<String>,
+    cargo_z: Vec<String>,
+    cargo_opts: CargoOptions,
+
+    /// Source to use instead of reading standard input when `script` is `-`.
+    script_source: Option<String>,
+    /// Capture the script's output, rather than letting it inherit ours.
+    capture_output: bool,
+}
+
+/**
+What happened when we ran a script.
+*/
+enum Ran {
+    /// Exited with this status, or we finished without running anything.
+    Status(i32),
+    /// The script's captured output.
+    Output(process::Output),
+}
+
+/**
+Switches that change how Cargo is run, but not what it builds, so they aren't part of the cache key.
+
+These start out from the `CARGO_SCRIPT_*` environment variables, which the command line and config file can then override.  They're passed along explicitly, rather than by setting those variables, so that running a script through `api` doesn't change the calling process's environment.
+*/
+#[derive(Clone, Debug, Default)]
+struct CargoOptions {
+    /// Value for `--color`: `auto`, `always`, or `never`.
+    color: Option<String>,
+    /// Turn incremental compilation on or off, rather than leaving it to Cargo.
+    incremental: Option<bool>,
+    /// Pass `--locked`?
+    locked: bool,
+    /// Pass `--offline`?
+    offline: bool,
+    /// Show each Cargo command before running it?
+    print_cmd: bool,
+    /// Pass `--quiet`?
+    quiet: bool,
+    /// Pass `--verbose`?
+    verbose: bool,
+    /// Compiler wrapper to set as `RUSTC_WRAPPER`.
+    rustc_wrapper: Option<OsString>,
+}
+
+impl CargoOptions {
+    /**
+    Reads the defaults from the environment.
+    */
+    fn from_env() -> CargoOptions {
+        CargoOptions {
+            color: std::env::var("CARGO_SCRIPT_COLOR").ok(),
+            incremental: match std::env::var("CARGO_SCRIPT_INCREMENTAL") {
+                Ok(ref v) if v == "" => None,
+                Ok(v) => Some(v != "0"),
+                Err(_) => None,
+            },
+            locked: env_flag("CARGO_SCRIPT_LOCKED"),
+            offline: env_flag("CARGO_SCRIPT_OFFLINE"),
+            print_cmd: env_flag("CARGO_SCRIPT_PRINT_CARGO_CMD"),
+            quiet: env_flag("CARGO_SCRIPT_QUIET"),
+            verbose: env_flag("CARGO_SCRIPT_VERBOSE"),
+            rustc_wrapper: std::env::var_os("CARGO_SCRIPT_RUSTC_WRAPPER")
+                .and_then(|w| if w.is_empty() { None } else { Some(w) }),
+        }
+    }
+}
+
+/**
+Whether a command that removes things should *actually* remove them.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum RemoveKind {
+    DryRun,
+    ForReal,
+}
+
+impl RemoveKind {
+    fn for_real(&self) -> bool {
+        *self == RemoveKind::ForReal
+    }
+}
+
+/**
+How to order the entries shown by `--list-cache`.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum CacheSort {
+    /// By package name.
+    Name,
+    /// Largest first.
+    Size,
+    /// Oldest first.
+    Age,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum BuildKind {
+    Normal,
+    Test,
+    Bench,
+    Check,
+}
+
+impl Default for BuildKind {
+    fn default() -> Self {
+        BuildKind::Normal
+    }
+}
+
+impl BuildKind {
+    fn can_exec_directly(&self) -> bool {
+        match *self {
+            BuildKind::Normal => true,
+            BuildKind::Test | BuildKind::Bench | BuildKind::Check => false,
+        }
+    }
+
+    fn exec_command(&self) -> &'static str {
+        match *self {
+            BuildKind::Normal => panic!("asked for exec command for normal build"),
+            BuildKind::Test => "test",
+            BuildKind::Bench => "bench",
+            BuildKind::Check => "check",
+        }
+    }
+
+    fn from_flags(test: bool, bench: bool, check: bool) -> Self {
+        match (test, bench, check) {
+            (false, false, false) => BuildKind::Normal,
+            (true, false, false) => BuildKind::Test,
+            (false, true, false) => BuildKind::Bench,
+            (false, false, true) => BuildKind::Check,
+            _ => panic!("got more than one of test, bench, and check")
+        }
+    }
+}
+
+/**
+How to print the result of an expression.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum OutputFormat {
+    /// With `{:?}`.
+    Debug,
+    /// With `{:#?}`.
+    Pretty,
+    /// With `{}`.
+    Display,
+    /// As JSON, using `serde_json`.
+    Json,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Debug
+    }
+}
+
+impl OutputFormat {
+    /**
+    Parses the value given to `--output-format`, or returns `None` if it isn't one we know.
+    */
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "debug" => Some(OutputFormat::Debug),
+            "pretty" => Some(OutputFormat::Pretty),
+            "display" => Some(OutputFormat::Display),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+
+    /**
+    The statement that prints `__cargo_script_expr`, or `None` if the template's default will do.
+    */
+    fn statement(&self) -> Option<&'static str> {
+        match *self {
+            OutputFormat::Debug => None,
+            OutputFormat::Pretty => Some(r#"println!("{:#?}", __cargo_script_expr)"#),
+            OutputFormat::Display => Some(r#"println!("{}", __cargo_script_expr)"#),
+            OutputFormat::Json => Some(r#"println!("{}", ::serde_json::to_string(&__cargo_script_expr)?)"#),
+        }
+    }
+}
+
+/**
+Parses the command line.  The global switches that affect how Cargo is run are returned separately, since they can be given alongside any subcommand.
+*/
+fn parse_args() -> (SubCommand, CargoOptions) {
+    use clap::{App, Arg, ArgGroup, SubCommand, AppSettings};
+    let version = option_env!("CARGO_PKG_VERSION").unwrap_or("unknown");
+    let about = r#"Compiles and runs "Cargoified Rust scripts"."#;
+
+    // "const str array slice"
+    macro_rules! csas {
+        ($($es:expr),*) => {
+            {
+                const PIN: &'static [&'static str] = &[$($es),*];
+                PIN
+            }
+        }
+    }
+
+    // We have to kinda lie about who we are for the output to look right...
+    let args = expand_hashbang_args(std::env::args_os().collect());
+    let args = expand_mode_arg(args);
+    let args = expand_toolchain_arg(args);
+    let bin_name = invoked_bin_name(&args);
+
+    // Working out the long version means running rustc, so only bother if it's going to be shown.
+    let long_version;
+    let version = if args.iter().skip(1).any(|a| a == "--version" || a == "-V") {
+        long_version = version_info(version);
+        &*long_version
+    } else {
+        version
+    };
+
+    let script_usage = format!("{} script [FLAGS OPTIONS] [--] <script> <args>...", bin_name);
+
+    let m = App::new(&*bin_name)
+        .bin_name(&*bin_name)
+        .version(version)
+        .about(about)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(Arg::with_name("cache_dir")
+            .help("Override the directory used to cache generated packages and compiled scripts.  Can also be set with the CARGO_SCRIPT_CACHE_DIR environment variable.")
+            .long("cache-dir")
+            .takes_value(true)
+            .global(true)
+        )
+        .arg(Arg::with_name("config_dir")
+            .help("Override the directory used to store configuration, such as templates.  Can also be set with the CARGO_SCRIPT_CONFIG_DIR environment variable.")
+            .long("config-dir")
+            .takes_value(true)
+            .global(true)
+        )
+        .arg(Arg::with_name("color")
+            .help("Whether Cargo should use coloured output.  `auto` uses colour if standard error is a terminal (never on Windows).  Can also be set with the CARGO_SCRIPT_COLOR environment variable.")
+            .long("color")
+            .takes_value(true)
+            .possible_values(csas!["auto", "always", "never"])
+            .global(true)
+        )
+        .arg(Arg::with_name("incremental")
+            .help("Build scripts with incremental compilation, which can speed up rebuilds of a script you're working on.  By default, Cargo decides (it's on for debug builds).  Can also be set with the CARGO_SCRIPT_INCREMENTAL environment variable.")
+            .long("incremental")
+            .global(true)
+            .conflicts_with("no_incremental")
+        )
+        .arg(Arg::with_name("no_incremental")
+            .help("Build scripts without incremental compilation.")
+            .long("no-incremental")
+            .global(true)
+            .conflicts_with("incremental")
+        )
+        .arg(Arg::with_name("locked")
+            .help("Require the script's `Cargo.lock` to be up to date.  This is passed on to Cargo.  Can also be set with the CARGO_SCRIPT_LOCKED environment variable.")
+            .long("locked")
+            .global(true)
+        )
+        .arg(Arg::with_name("offline")
+            .help("Forbid network access.  This is passed on to Cargo, so dependencies must already be available locally.  Can also be set with the CARGO_SCRIPT_OFFLINE environment variable.")
+            .long("offline")
+            .global(true)
+        )
+        .arg(Arg::with_name("print_cargo_cmd")
+            .help("Print each Cargo command, along with any environment variables set for it, to standard error before running it.  Can also be set with the CARGO_SCRIPT_PRINT_CARGO_CMD environment variable.")
+            .long("print-cargo-cmd")
+            .global(true)
+        )
+        .arg(Arg::with_name("quiet")
+            .help("Don't show Cargo's build output; only the script's own output and errors.  Can also be set with the CARGO_SCRIPT_QUIET environment variable.")
+            .long("quiet")
+            .short("q")
+            .global(true)
+            .conflicts_with("verbose")
+        )
+        .arg(Arg::with_name("rustc_wrapper")
+            .help("Run rustc through this wrapper, such as `sccache`, when building scripts.  Passed on to Cargo as RUSTC_WRAPPER; one that's already set is used otherwise.  Can also be set with the CARGO_SCRIPT_RUSTC_WRAPPER environment variable.")
+            .long("rustc-wrapper")
+            .value_name("PATH")
+            .takes_value(true)
+            .global(true)
+        )
+        .arg(Arg::with_name("verbose")
+            .help("Show more detail: Cargo is run with `--verbose`, and cargo-script logs what it's doing (unless RUST_LOG says otherwise).  Can also be set with the CARGO_SCRIPT_VERBOSE environment variable.")
+            .long("verbose")
+            .short("v")
+            .global(true)
+        )
+        .subcommand(SubCommand::with_name("script")
+            .version(version)
+            .about(about)
+            .usage(&*script_usage)
+
+            /*
+            Major script modes.
+            */
+            .arg(Arg::with_name("script")
+                .help("Script file (with or without extension) to execute.  Use `-` to read the script from standard input.")
+                .index(1)
+            )
+            .arg(Arg::with_name("args")
+                .help("Additional arguments passed to the script.")
+                .index(2)
+                .multiple(true)
+            )
+            .arg(Arg::with_name("expr")
+                .help("Execute <expr> as a literal expression and display the result.  Given more than once, all but the last are run as statements first.")
+                .long("expr")
+                .short("e")
+                .value_name("expr")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .min_values(0)
+                .conflicts_with_all(csas!["loop"])
+            )
+            .arg(Arg::with_name("file")
+                .help("Treat <script> as a complete program.  Normally, a script without a top-level `fn main` is treated as an expression.")
+                .long("file")
+                .conflicts_with_all(csas!["expr", "loop"])
+                .requires("script")
+            )
+            .arg(Arg::with_name("loop")
+                .help("Execute <script> as a literal closure once for each line from stdin.")
+                .long("loop")
+                .short("l")
+                .conflicts_with_all(csas!["expr", "stdin_as", "exit_expr"])
+                .requires("script")
+            )
+            .arg(Arg::with_name("interactive")
+                .help("Read expressions from stdin, one per line, and evaluate each in turn.  Dependencies are only built once, so after the first, each expression builds quickly.")
+                .long("interactive")
+                .short("i")
+                .conflicts_with_all(csas!["script", "args", "expr", "count", "stdin_as", "exit_expr"])
+            )
+            .group(ArgGroup::with_name("expr_or_loop")
+                .args(&["expr", "loop", "interactive"])
+            )
+            .group(ArgGroup::with_name("script_or_expr")
+                .args(&["script", "expr"])
+                .multiple(true)
+            )
+            .group(ArgGroup::with_name("script_or_interactive")
+                .args(&["script", "expr", "interactive"])
+                .multiple(true)
+            )
+
+            /*
+            Options that impact the script being executed.
+            */
+            .arg(Arg::with_name("backtrace")
+                .help("Show a backtrace if the script panics, by setting RUST_BACKTRACE for it.  Use `--backtrace=full` for a full backtrace.")
+                .long("backtrace")
+                .value_name("full")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .possible_values(csas!["full"])
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("count")
+                .help("Invoke the loop closure with two arguments: line, and line number.")
+                .long("count")
+                .requires("loop")
+            )
+            .arg(Arg::with_name("debug")
+                .help("Build a debug executable, not an optimised one.")
+                .long("debug")
+                .conflicts_with_all(csas!["profile"])
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("debug_symbols")
+                .help("Keep debug symbols in an optimised executable, for profiling.")
+                .long("debug-symbols")
+                .requires("script_or_interactive")
+                .conflicts_with_all(csas!["debug", "strip"])
+            )
+            .arg(Arg::with_name("dep")
+                .help("Add an additional Cargo dependency.  Each SPEC can be just the package name (which will assume the latest version), a full `name=version` spec, `name=path:DIR` for a local crate, or `name=git:URL#REF` for a git repository (`#REF` is optional).")
+                .long("dep")
+                .short("d")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("dep_extern")
+                .help("Like `dep`, except that it *also* adds a `#[macro_use] extern crate name;` item for expression and loop scripts.  Note that this only works if the name of the dependency and the name of the library it generates are exactly the same.")
+                .long("dep-extern")
+                .short("D")
+                .takes_value(true)
+                .multiple(true)
+                .requires("expr_or_loop")
+            )
+            .arg(Arg::with_name("dep_features")
+                .help("Enable features for a dependency given with `dep` or `dep-extern`, as `name=feature,feature`.  Start the list with `-` to also disable the dependency's default features.")
+                .long("dep-features")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("dep_no_default")
+                .help("Disable the default features of a dependency given with `dep` or `dep-extern`.")
+                .long("dep-no-default")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("edition")
+                .help("Rust edition to compile the script with.  If not given, Cargo's default (2015) is used.")
+                .long("edition")
+                .takes_value(true)
+                .possible_values(csas!["2015", "2018", "2021"])
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("env")
+                .help("Set an environment variable, given as `KEY=VALUE`, for the script when it runs.  This doesn't affect the build.  Can be given more than once.")
+                .long("env")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|s| env_pair(&s).map(|_| ()))
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("expr_prelude")
+                .help("Add code, such as a `use` item, to the prelude of an expression or loop script.  Can be given more than once; they're added in order.")
+                .long("expr-prelude")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("expr_or_loop")
+            )
+            .arg(Arg::with_name("prelude_file")
+                .help("Add the contents of this file to the prelude of an expression or loop script, ahead of any `--expr-prelude` code.  Defaults to `prelude.rs` in the config directory, if it exists.")
+                .long("prelude-file")
+                .value_name("PATH")
+                .takes_value(true)
+                .requires("expr_or_loop")
+            )
+            .arg(Arg::with_name("extern")
+                .help("Adds an `#[macro_use] extern crate name;` item for expressions and loop scripts.")
+                .long("extern")
+                .short("x")
+                .takes_value(true)
+                .multiple(true)
+                .requires("expr_or_loop")
+            )
+            .arg(Arg::with_name("extern_glob")
+                .help("Like `--extern`, but also adds a `use path::*;` item.  The path is either just the crate name, or something inside it, like `name::prelude`.")
+                .long("extern-glob")
+                .short("X")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|s| extern_glob_items(&s).map(|_| ()))
+                .requires("expr_or_loop")
+            )
+            .arg(Arg::with_name("input")
+                .help("Read this file into a `String` called `input`, which expressions and loop scripts can use.  It's read each time the script runs, so changing it doesn't cause a rebuild.")
+                .long("input")
+                .value_name("PATH")
+                .takes_value(true)
+                .requires("expr_or_loop")
+            )
+            .arg(Arg::with_name("output_marker")
+                .help("Write marker lines to standard output immediately before and after the script runs, so that its output can be told apart from anything else written there.")
+                .long("output-marker")
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("output_format")
+                .help("How to print the result of the expression: with `{:?}` (`debug`, the default), `{:#?}` (`pretty`), `{}` (`display`), or as JSON (`json`).  `json` adds a dependency on `serde_json`.")
+                .long("output-format")
+                .takes_value(true)
+                .possible_values(csas!["debug", "pretty", "display", "json"])
+                .requires("expr_or_loop")
+                .conflicts_with_all(csas!["loop", "exit_expr"])
+            )
+            .arg(Arg::with_name("exit_expr")
+                .help("Exit with the value of the expression, which must be an `i32`, instead of printing it.")
+                .long("exit-expr")
+                .requires("expr")
+                .conflicts_with_all(csas!["loop", "interactive", "output_format"])
+            )
+            .arg(Arg::with_name("stdin_as")
+                .help("Read all of standard input into a `String` with this name before evaluating the expression.")
+                .long("stdin-as")
+                .value_name("VAR")
+                .takes_value(true)
+                .validator(|s| check_ident(&s).map_err(|err| format!("invalid variable name `{}`: {}", s, err)))
+                .requires("expr")
+                .conflicts_with_all(csas!["loop"])
+            )
+            .arg(Arg::with_name("all_features")
+                .help("Enable all of the script's own features.")
+                .long("all-features")
+                .requires("script_or_expr")
+            )
+            .arg(Arg::with_name("features")
+                 .help("Cargo features to pass when building and running.")
+                 .long("features")
+                 .takes_value(true)
+            )
+            .arg(Arg::with_name("no_default_features")
+                .help("Disable the script's own default features.")
+                .long("no-default-features")
+                .requires("script_or_expr")
+            )
+            .arg(Arg::with_name("release")
+                .help("Build an optimised executable.  This is the default; it's accepted so that `--release` does what Cargo users expect.")
+                .long("release")
+                .conflicts_with_all(csas!["debug", "profile"])
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("profile")
+                .help("Build with this Cargo profile, such as one defined in the script's manifest.  Requires Cargo 1.57 or later.")
+                .long("profile")
+                .value_name("NAME")
+                .takes_value(true)
+                .conflicts_with_all(csas!["debug", "release"])
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("strip")
+                .help("Strip symbols from the executable, making it smaller.")
+                .long("strip")
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("rustc_flags")
+                .help("Pass an extra flag to rustc when building the script, such as `-C target-cpu=native`.  Can be given more than once.")
+                .long("rustc-flag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .allow_hyphen_values(true)
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("cargo_z")
+                .help("Pass an unstable `-Z` flag, such as `build-std`, to Cargo.  Can be given more than once.  Only used with a nightly toolchain.")
+                .long("cargo-z")
+                .short("Z")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("cfg")
+                .help("Set a configuration option, such as `feature_x` or `mode=fast`, for `#[cfg(...)]` in the script.  Can be given more than once.")
+                .long("cfg")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|s| cfg_rustc_flag(&s).map(|_| ()))
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("deny")
+                .help("Make a lint, such as `warnings` or `unused_variables`, an error.  Can be given more than once.")
+                .long("deny")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|s| lint_rustc_flag("-D", &s).map(|_| ()))
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("warn")
+                .help("Make a lint a warning.  Can be given more than once.  Overrides `--deny`.")
+                .long("warn")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|s| lint_rustc_flag("-W", &s).map(|_| ()))
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("allow")
+                .help("Silence a lint.  Can be given more than once.  Overrides `--deny` and `--warn`.")
+                .long("allow")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|s| lint_rustc_flag("-A", &s).map(|_| ()))
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("target")
+                .help("Build the script for the given target triple.  The result won't be run; its path is printed instead.")
+                .long("target")
+                .takes_value(true)
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["args"])
+            )
+            .arg(Arg::with_name("toolchain")
+                .help("Build the script with a specific rustup toolchain.  `+TOOLCHAIN` before the script can also be used.")
+                .long("toolchain")
+                .takes_value(true)
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("unstable_features")
+                .help("Add a #![feature] declaration to the crate.")
+                .long("unstable-feature")
+                .short("u")
+                .takes_value(true)
+                .multiple(true)
+                .requires("expr_or_loop")
+            )
+
+            /*
+            Options that change how cargo script itself behaves, and don't alter what the script will do.
+            */
+            .arg(Arg::with_name("build_only")
+                .help("Build the script, but don't run it.")
+                .long("build-only")
+                .alias("no-run")
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["args", "run_cached"])
+            )
+            .arg(Arg::with_name("run_cached")
+                .help("Run the cached build of the script without building it.  If there's no up-to-date cached build, this is an error.")
+                .long("run-cached")
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["build_only", "force", "update", "gen_pkg_only", "extract", "prime", "test", "bench", "check"])
+            )
+            .arg(Arg::with_name("bin")
+                .help("Run the binary with this name, if the script's manifest defines more than one.")
+                .long("bin")
+                .value_name("NAME")
+                .takes_value(true)
+                .requires("script_or_expr")
+            )
+            .arg(Arg::with_name("crate_type")
+                .help("Build the script as a library of this crate type, rather than as an executable, and print the path to what was built instead of running it.  Implies --file.")
+                .long("crate-type")
+                .value_name("TYPE")
+                .takes_value(true)
+                .possible_values(csas!["cdylib", "dylib", "rlib", "staticlib"])
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["args", "bin", "expr", "loop", "extract", "install", "prime", "test", "bench"])
+            )
+            .arg(Arg::with_name("clear_cache")
+                .help("Clears out the script and binary caches, along with any scripts read from standard input.")
+                .long("clear-cache")
+            )
+            .arg(Arg::with_name("dry_run")
+                .help("With --clear-cache, report what would be removed without removing anything.")
+                .long("dry-run")
+                .requires("clear_cache")
+                .conflicts_with_all(csas!["script", "expr"])
+            )
+            .arg(Arg::with_name("dump_expanded")
+                .help("Write the generated source to stderr before compiling it.")
+                .long("dump-expanded")
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("explain_cache_decision")
+                .help("Explain why the script is (or isn't) being rebuilt.")
+                .long("explain-cache-decision")
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("extract")
+                .help("Write the generated package out to this directory as an ordinary Cargo project, with the source in `src/main.rs`, but don't build or run it.  Existing files are only replaced if `--force` is also given.")
+                .long("extract")
+                .value_name("DIR")
+                .takes_value(true)
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["args", "build_only", "gen_pkg_only", "install", "pkg_path", "print_binary_path", "run_cached", "test", "bench", "check"])
+            )
+            .arg(Arg::with_name("force")
+                .help("Force the script to be rebuilt, even if a cached build looks up to date.  The cache is updated with the new build.")
+                .long("force")
+                .short("f")
+                .alias("force-rebuild")
+                .requires("script_or_interactive")
+                .conflicts_with_all(csas!["run_cached"])
+            )
+            .arg(Arg::with_name("format")
+                .help("Run the generated source through rustfmt before compiling, if it's available.")
+                .long("format")
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("gen_pkg_only")
+                .help("Generate the Cargo package, but don't compile or run it.")
+                .long("gen-pkg-only")
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["args", "build_only", "debug", "force", "run_cached", "test", "bench"])
+            )
+            .arg(Arg::with_name("install")
+                .help("Build the script, then copy the executable into Cargo's `bin` directory.  An existing executable is only replaced if `--overwrite` is also given.")
+                .long("install")
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["args", "expr", "loop", "gen_pkg_only", "print_binary_path", "target", "test", "bench", "check"])
+            )
+            .arg(Arg::with_name("install_dir")
+                .help("Install into this directory instead of Cargo's `bin` directory.")
+                .long("install-dir")
+                .takes_value(true)
+                .requires("install")
+            )
+            .arg(Arg::with_name("install_name")
+                .help("Name to install the executable as.  Defaults to the name of the script.")
+                .long("install-name")
+                .takes_value(true)
+                .requires("install")
+            )
+            .arg(Arg::with_name("install_overwrite")
+                .help("With --install, replace an executable that's already there.")
+                .long("overwrite")
+                .requires("install")
+            )
+            .arg(Arg::with_name("keep_temp")
+                .help("Keep the generated Cargo package, even if the build fails, and print where it is.")
+                .long("keep-temp")
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("list_cache")
+                .help("List the scripts in the cache, with how much space each takes up and how long ago it was built.")
+                .long("list-cache")
+                .conflicts_with_all(csas!["script", "clear_cache"])
+            )
+            .arg(Arg::with_name("list_cache_sort")
+                .help("With --list-cache, sort by package name, by size (largest first), or by age (oldest first).")
+                .long("sort")
+                .takes_value(true)
+                .possible_values(csas!["name", "size", "age"])
+                .requires("list_cache")
+            )
+            .arg(Arg::with_name("manifest")
+                .help("Merge a fragment of TOML into the generated package's manifest, such as `[profile.dev]` settings.  The script's own manifest takes precedence.  Can be given more than once.")
+                .long("manifest")
+                .value_name("TOML")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("message_format")
+                .help("Format of Cargo's build messages.  With `json`, Cargo's JSON messages are written to standard output before the script runs.")
+                .long("message-format")
+                .takes_value(true)
+                .possible_values(csas!["human", "json"])
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("post_run_hook")
+                .help("Shell command to run after the script has run, whether or not it succeeded.  The script and package paths are passed in `CARGO_SCRIPT_SCRIPT_PATH` and `CARGO_SCRIPT_PKG_PATH`.")
+                .long("post-run-hook")
+                .value_name("CMD")
+                .takes_value(true)
+                .requires("script_or_expr")
+            )
+            .arg(Arg::with_name("pre_build_hook")
+                .help("Shell command to run before the script is built.  If it fails, so does cargo-script.  The script and package paths are passed in `CARGO_SCRIPT_SCRIPT_PATH` and `CARGO_SCRIPT_PKG_PATH`.")
+                .long("pre-build-hook")
+                .value_name("CMD")
+                .takes_value(true)
+                .requires("script_or_expr")
+            )
+            .arg(Arg::with_name("prime")
+                .help("Build the script's dependencies, but not the script itself, so that a later run only has to compile the script.")
+                .long("prime")
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["args", "build_only", "extract", "gen_pkg_only", "install", "print_binary_path", "run_cached", "test", "bench", "check"])
+            )
+            .arg(Arg::with_name("print_binary_path")
+                .help("Build the script, then print the path to the executable instead of running it.")
+                .long("print-binary-path")
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["args", "gen_pkg_only", "test", "bench", "check"])
+            )
+            .arg(Arg::with_name("name")
+                .help("Name to give the generated package, and so the executable.  Must be a valid Rust identifier.  Defaults to the script's file name, or `expr` or `loop`.")
+                .long("name")
+                .takes_value(true)
+                .requires("script_or_expr")
+            )
+            .arg(Arg::with_name("pkg_path")
+                .help("Specify where to place the generated Cargo package.")
+                .long("pkg-path")
+                .alias("work-dir")
+                .takes_value(true)
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["clear_cache", "force"])
+            )
+            .arg(Arg::with_name("run_dir")
+                .help("Directory to run the script in.  Defaults to the current directory; the build always happens in the cache.")
+                .long("run-dir")
+                .takes_value(true)
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["test", "bench"])
+            )
+            .arg(Arg::with_name("timeout")
+                .help("Kill the script, along with anything it started, if it runs for longer than this many seconds.  On Unix, if standard input is a terminal, only the script itself is killed, so that it can still use the terminal.")
+                .long("timeout")
+                .value_name("SECS")
+                .takes_value(true)
+                .requires("script_or_interactive")
+                .validator(|s| match s.parse::<u64>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err(format!("invalid timeout `{}`: expected a whole number of seconds greater than zero", s)),
+                })
+            )
+            .arg(Arg::with_name("update")
+                .help("Update the script's dependencies to the latest compatible versions, rather than reusing those in its `Cargo.lock`.  This forces a rebuild.")
+                .long("update")
+                .requires("script_or_interactive")
+                .conflicts_with_all(csas!["locked", "run_cached"])
+            )
+            .arg(Arg::with_name("use_bincache")
+                .help("Override whether or not the shared binary cache will be used for compilation.")
+                .long("use-shared-binary-cache")
+                .takes_value(true)
+                .possible_values(csas!["no", "yes"])
+            )
+            .arg(Arg::with_name("warn_dirty_deps")
+                .help("Warn if a path dependency is a git working copy with uncommitted changes, since the build then can't be reproduced from what's committed.")
+                .long("warn-dirty-deps")
+                .requires("script_or_interactive")
+            )
+            .arg(Arg::with_name("migrate_data")
+                .help("Migrate data from older versions.  Prefer the `migrate` subcommand.")
+                .long("migrate-data")
+                .takes_value(true)
+                .possible_values(csas!["dry-run", "for-real"])
+            )
+            .arg(Arg::with_name("test")
+                .help("Compile and run tests.  Any arguments after the script are passed to the test harness.")
+                .long("test")
+                .conflicts_with_all(csas!["bench", "debug", "force", "run_cached"])
+            )
+            .arg(Arg::with_name("bench")
+                .help("Compile and run benchmarks.  Requires a nightly toolchain.  Any arguments after the script are passed to the benchmark harness.")
+                .long("bench")
+                .conflicts_with_all(csas!["test", "debug", "force", "run_cached"])
+            )
+            .arg(Arg::with_name("check")
+                .help("Check the script for errors with `cargo check`, but don't build or run it.")
+                .long("check")
+                .requires("script_or_expr")
+                .conflicts_with_all(csas!["test", "bench", "args", "force", "build_only", "run_cached"])
+            )
+            .arg(Arg::with_name("template")
+                .help("Specify a template to use for expression scripts.")
+                .long("template")
+                .short("t")
+                .takes_value(true)
+                .requires("expr_or_loop")
+                .conflicts_with_all(csas!["loop"])
+            )
+        )
+        .subcommands(script_subcommands().into_iter().map(|(_, app)| app))
+        .get_matches_from(args);
+
+    /*
+    The directory overrides are global, so they could be attached to whichever (sub)subcommand they were given after.  They're passed on via the environment, since that's where `platform` looks for them anyway, and every subcommand needs them.  This is the executable's own environment; nothing in `api` does this.
+    */
+    fn global_value_of<'a>(m: &'a clap::ArgMatches, name: &str) -> Option<&'a str> {
+        m.value_of(name)
+            .or_else(|| m.subcommand().1.and_then(|m| global_value_of(m, name)))
+    }
+
+    if let Some(dir) = global_value_of(&m, "cache_dir") {
+        std::env::set_var("CARGO_SCRIPT_CACHE_DIR", dir);
+    }
+    if let Some(dir) = global_value_of(&m, "config_dir") {
+        std::env::set_var("CARGO_SCRIPT_CONFIG_DIR", dir);
+    }
+
+    fn global_is_present(m: &clap::ArgMatches, name: &str) -> bool {
+        m.is_present(name)
+            || m.subcommand().1.map(|m| global_is_present(m, name)).unwrap_or(false)
+    }
+
+    let mut cargo_opts = CargoOptions::from_env();
+    if let Some(color) = global_value_of(&m, "color") {
+        cargo_opts.color = Some(color.into());
+    }
+    if global_is_present(&m, "incremental") {
+        cargo_opts.incremental = Some(true);
+    } else if global_is_present(&m, "no_incremental") {
+        cargo_opts.incremental = Some(false);
+    }
+    if global_is_present(&m, "locked") {
+        cargo_opts.locked = true;
+    }
+    if global_is_present(&m, "offline") {
+        cargo_opts.offline = true;
+    }
+    if global_is_present(&m, "print_cargo_cmd") {
+        cargo_opts.print_cmd = true;
+    }
+    if global_is_present(&m, "quiet") {
+        cargo_opts.quiet = true;
+    }
+    if let Some(wrapper) = global_value_of(&m, "rustc_wrapper") {
+        cargo_opts.rustc_wrapper = if wrapper == "" { None } else { Some(wrapper.into()) };
+    }
+    if global_is_present(&m, "verbose") {
+        cargo_opts.verbose = true;
+    }
+
+    if let Some(m) = m.subcommand_matches(doctor::NAMES[0]) {
+        return (::SubCommand::Doctor(doctor::Args::parse(m, cargo_opts.color.clone())), cargo_opts);
+    }
+
+    if let Some(m) = m.subcommand_matches(gc::NAMES[0]) {
+        return (::SubCommand::Gc(gc::Args::parse(m)), cargo_opts);
+    }
+
+    if let Some(m) = m.subcommand_matches(migrate::NAMES[0]) {
+        return (::SubCommand::Migrate(migrate::Args::parse(m)), cargo_opts);
+    }
+
+    if let Some(m) = m.subcommand_matches(templates::NAMES[0]) {
+        return (::SubCommand::Templates(templates::Args::parse(m)), cargo_opts);
+    }
+
+    if_windows! {
+        if let Some(m) = m.subcommand_matches(file_assoc::NAMES[0]) {
+            return (::SubCommand::FileAssoc(file_assoc::Args::parse(m)), cargo_opts);
+        }
+    }
+
+    let m = m.subcommand_matches("script").unwrap();
+
+    fn owned_vec_string<'a, I>(v: Option<I>) -> Vec<String>
+    where I: ::std::iter::Iterator<Item=&'a str> {
+        v.map(|itr| itr.map(Into::into).collect()).unwrap_or(vec![])
+    }
+
+    fn yes_or_no(v: Option<&str>) -> Option<bool> {
+        v.map(|v| match v {
+            "yes" => true,
+            "no" => false,
+            _ => unreachable!()
+        })
+    }
+
+    fn run_kind(v: Option<&str>) -> Option<MigrationKind> {
+        v.map(|v| match v {
+            "dry-run" => MigrationKind::DryRun,
+            "for-real" => MigrationKind::ForReal,
+            _ => unreachable!()
+        })
+    }
+
+    /*
+    With `-e EXPR`, the expressions are the script, so every positional argument is meant for it.  A bare `-e` (say, `-e --dep x EXPR`) still works the old way, with the expression taken from `<script>`.
+    */
+    let exprs = owned_vec_string(m.values_of("expr"));
+    let (script, script_args) = if exprs.len() > 0 {
+        let script_args = m.value_of("script").into_iter().chain(m.values_of("args").into_iter().flat_map(|v| v));
+        (Some(join_exprs(&exprs)), script_args.map(Into::into).collect())
+    } else {
+        (m.value_of("script").map(Into::into), owned_vec_string(m.values_of("args")))
+    };
+
+    let args = Args {
+        script: script,
+        args: script_args,
+        features: m.value_of("features").map(Into::into),
+        all_features: m.is_present("all_features"),
+        no_default_features: m.is_present("no_default_features"),
+        edition: m.value_of("edition").map(Into::into),
+        default_edition: None,
+        env: m.values_of("env")
+            .map(|vs| vs.map(|v| env_pair(v).unwrap()).collect())
+            .unwrap_or(vec![]),
+        backtrace: if m.is_present("backtrace") {
+            Some(m.value_of("backtrace").unwrap_or("1").into())
+        } else {
+            None
+        },
+        rustc_flags: {
+            // `--cfg`s are just a friendlier way of writing rustc flags.
+            let cfgs = m.values_of("cfg").into_iter().flat_map(|v| v)
+                .map(|s| cfg_rustc_flag(s).unwrap());
+            let mut flags: Vec<String> = cfgs.collect();
+
+            /*
+            So are lint levels.  rustc lets later flags override earlier ones, so these go from strictest to least strict; that way, `--deny warnings --allow dead_code` does what you'd hope.
+            */
+            for &(name, flag) in &[("deny", "-D"), ("warn", "-W"), ("allow", "-A")] {
+                flags.extend(m.values_of(name).into_iter().flat_map(|v| v)
+                    .map(|s| lint_rustc_flag(flag, s).unwrap()));
+            }
+
+            flags.extend(owned_vec_string(m.values_of("rustc_flags")));
+            flags
+        },
+        target: m.value_of("target").map(Into::into),
+        toolchain: m.value_of("toolchain").map(Into::into),
+
+        expr: m.is_present("expr"),
+        // Test and benchmark harnesses supply their own `main`, so scripts run that way often don't have one.
+        file: m.is_present("file") || m.is_present("crate_type") || m.is_present("test") || m.is_present("bench"),
+        loop_: m.is_present("loop"),
+        count: m.is_present("count"),
+
+        pkg_path: m.value_of("pkg_path").map(Into::into),
+        gen_pkg_only: m.is_present("gen_pkg_only"),
+        build_only: m.is_present("build_only"),
+        run_cached: m.is_present("run_cached"),
+        bin: m.value_of("bin").map(Into::into),
+        crate_type: m.value_of("crate_type").map(Into::into),
+        clear_cache: match (m.is_present("clear_cache"), m.is_present("dry_run")) {
+            (false, _) => None,
+            (true, false) => Some(RemoveKind::ForReal),
+            (true, true) => Some(RemoveKind::DryRun),
+        },
+        debug: m.is_present("debug"),
+        release: m.is_present("release"),
+        profile: m.value_of("profile").map(Into::into),
+        debug_symbols: m.is_present("debug_symbols"),
+        dep: owned_vec_string(m.values_of("dep")),
+        dep_extern: owned_vec_string(m.values_of("dep_extern")),
+        dep_features: owned_vec_string(m.values_of("dep_features")),
+        dep_no_default: owned_vec_string(m.values_of("dep_no_default")),
+        dump_expanded: m.is_present("dump_expanded"),
+        explain_cache_decision: m.is_present("explain_cache_decision"),
+        expr_prelude: owned_vec_string(m.values_of("expr_prelude")),
+        prelude_file: m.value_of("prelude_file").map(Into::into),
+        extern_: owned_vec_string(m.values_of("extern")),
+        extern_glob: owned_vec_string(m.values_of("extern_glob")),
+        input: m.value_of("input").map(Into::into),
+        interactive: m.is_present("interactive"),
+        stdin_as: m.value_of("stdin_as").map(Into::into),
+        output_format: m.value_of("output_format").and_then(OutputFormat::from_str).unwrap_or(OutputFormat::default()),
+        output_marker: m.is_present("output_marker"),
+        exit_expr: m.is_present("exit_expr"),
+        extract: m.value_of("extract").map(Into::into),
+        force: m.is_present("force"),
+        format: m.is_present("format"),
+        install: m.is_present("install"),
+        install_dir: m.value_of("install_dir").map(Into::into),
+        install_name: m.value_of("install_name").map(Into::into),
+        install_overwrite: m.is_present("install_overwrite"),
+        json_messages: m.value_of("message_format") == Some("json"),
+        keep_temp: m.is_present("keep_temp"),
+        list_cache: match (m.is_present("list_cache"), m.value_of("list_cache_sort")) {
+            (false, _) => None,
+            (true, Some("size")) => Some(CacheSort::Size),
+            (true, Some("age")) => Some(CacheSort::Age),
+            (true, _) => Some(CacheSort::Name),
+        },
+        manifest: owned_vec_string(m.values_of("manifest")),
+        name: m.value_of("name").map(Into::into),
+        post_run_hook: m.value_of("post_run_hook").map(Into::into),
+        pre_build_hook: m.value_of("pre_build_hook").map(Into::into),
+        prime: m.is_present("prime"),
+        print_binary_path: m.is_present("print_binary_path") || m.is_present("crate_type"),
+        run_dir: m.value_of("run_dir").map(Into::into),
+        strip: m.is_present("strip"),
+        timeout: m.value_of("timeout").map(|s| s.parse().unwrap()),
+        unstable_features: owned_vec_string(m.values_of("unstable_features")),
+        update: m.is_present("update"),
+        use_bincache: yes_or_no(m.value_of("use_bincache")),
+        warn_dirty_deps: m.is_present("warn_dirty_deps"),
+        migrate_data: run_kind(m.value_of("migrate_data")),
+        build_kind: BuildKind::from_flags(m.is_present("test"), m.is_present("bench"), m.is_present("check")),
+        template: m.value_of("template").map(Into::into),
+        cargo_z: owned_vec_string(m.values_of("cargo_z")),
+        cargo_opts: cargo_opts.clone(),
+
+        script_source: None,
+        capture_output: false,
+    };
+    (::SubCommand::Script(args), cargo_opts)
+}
+
+/**
+Works out what we should call ourselves in usage and help text.
+
+When run as `cargo script`, Cargo invokes us as `cargo-script script ...`, in which case we pretend to be `cargo`.  Otherwise, we've been run directly, and should use whatever name we were invoked with.
+*/
+fn invoked_bin_name(args: &[OsString]) -> String {
+    if args.get(1).and_then(|a| a.to_str()) == Some("script") {
+        return "cargo".into();
+    }
+
+    args.get(0)
+        .and_then(|a| Path::new(a).file_stem())
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "cargo-script".into())
+}
+
+#[test]
+fn test_invoked_bin_name() {
+    macro_rules! ibn {
+        ($($args:expr),*) => (invoked_bin_name(&[$(OsString::from($args)),*]))
+    }
+
+    assert_eq!(ibn!["/usr/bin/cargo-script", "script", "foo.rs"], "cargo");
+    assert_eq!(ibn!["cargo-script", "script"], "cargo");
+    assert_eq!(ibn!["/usr/bin/cargo-script", "templates", "list"], "cargo-script");
+    assert_eq!(ibn!["cargo-script.exe", "--help"], "cargo-script");
+    assert_eq!(ibn!["cargo-script"], "cargo-script");
+    assert_eq!(ibn![], "cargo-script");
+}
+
+/**
+Returns the subcommands of `script`, along with every name each one answers to.
+
+The names come from the same `NAMES` list each subcommand is defined with.  They're used both to build the argument parser, and to work out which arguments `expand_hashbang_args` must leave alone.
+*/
+fn script_subcommands() -> Vec<(&'static [&'static str], clap::App<'static, 'static>)> {
+    #[allow(unused_mut)]
+    let mut subcommands = vec![
+        (doctor::NAMES, doctor::Args::subcommand()),
+        (gc::NAMES, gc::Args::subcommand()),
+        (migrate::NAMES, migrate::Args::subcommand()),
+        (templates::NAMES, templates::Args::subcommand()),
+    ];
+    if_windows! {
+        subcommands.push((file_assoc::NAMES, file_assoc::Args::subcommand()));
+    }
+    subcommands
+}
+
+/**
+Returns every name, including aliases, that selects a subcommand rather than a script.
+*/
+fn subcommand_names() -> Vec<&'static str> {
+    let mut names = vec!["script", "help"];
+    for (sub_names, _) in script_subcommands() {
+        names.extend(sub_names.iter().cloned());
+    }
+    names
+}
+
+/**
+Rewrites the arguments for when we've been used directly as a hashbang interpreter.
+
+With `#!/usr/bin/env cargo-script`, we get invoked as `cargo-script PATH ARGS...`.  If the first argument isn't a subcommand or a flag, but *is* an existing file, we assume that's what happened and turn it into `cargo-script script PATH -- ARGS...`, so that none of the script's arguments are mistaken for ours.
+*/
+fn expand_hashbang_args(args: Vec<OsString>) -> Vec<OsString> {
+    let is_script = match args.get(1) {
+        Some(arg) => {
+            let flag_or_subcommand = arg.to_str()
+                .map(|a| a.starts_with("-") || subcommand_names().contains(&a))
+                .unwrap_or(false);
+            !flag_or_subcommand && Path::new(arg).is_file_polyfill()
+        },
+        None => false,
+    };
+    if !is_script {
+        return args;
+    }
+
+    let mut args = args.into_iter();
+    let mut new_args: Vec<OsString> = args.next().into_iter().collect();
+    new_args.push("script".into());
+    new_args.extend(args.next());
+    new_args.push("--".into());
+    new_args.extend(args);
+    new_args
+}
+
+#[test]
+fn test_expand_hashbang_args() {
+    macro_rules! eha {
+        ($($args:expr),*) => (expand_hashbang_args(vec![$(OsString::from($args)),*]))
+    }
+
+    macro_rules! osv {
+        ($($args:expr),*) => (vec![$(OsString::from($args)),*] as Vec<OsString>)
+    }
+
+    assert_eq!(eha!["cargo-script", "tests/data/script-no-deps.rs"],
+        osv!["cargo-script", "script", "tests/data/script-no-deps.rs", "--"]);
+    assert_eq!(eha!["cargo-script", "tests/data/script-no-deps.rs", "--help", "x"],
+        osv!["cargo-script", "script", "tests/data/script-no-deps.rs", "--", "--help", "x"]);
+    assert_eq!(eha!["cargo-script", "script", "tests/data/script-no-deps.rs"],
+        osv!["cargo-script", "script", "tests/data/script-no-deps.rs"]);
+    assert_eq!(eha!["cargo-script", "tests/data/does-not-exist.rs"],
+        osv!["cargo-script", "tests/data/does-not-exist.rs"]);
+    assert_eq!(eha!["cargo-script", "tests/data"], osv!["cargo-script", "tests/data"]);
+    assert_eq!(eha!["cargo-script", "--help"], osv!["cargo-script", "--help"]);
+    assert_eq!(eha!["cargo-script"], osv!["cargo-script"]);
+
+    // A file that happens to share a subcommand's name mustn't shadow it.
+    let names = subcommand_names();
+    for name in &["script", "help", "doctor", "check-env", "gc", "migrate", "templates"] {
+        assert!(names.iter().any(|n| n == name), "missing subcommand {:?}", name);
+    }
+    assert_eq!(eha!["cargo-script", "migrate", "--dry-run"],
+        osv!["cargo-script", "migrate", "--dry-run"]);
+    assert_eq!(eha!["cargo-script", "check-env"], osv!["cargo-script", "check-env"]);
+}
+
+/**
+Reads the prelude file for expressions and loop scripts: the one given with `--prelude-file`, or else the one in the config directory, if there is one.
+*/
+fn read_prelude_file(path: Option<&str>) -> Result<Option<String>> {
+    let path = match path {
+        Some(path) => try!(std::env::current_dir()).join(util::expand_tilde(path)),
+        None => match platform::get_config_dir() {
+            Ok(dir) => {
+                let path = dir.join(consts::PRELUDE_FILE);
+                if !path.is_file_polyfill() {
+                    return Ok(None);
+                }
+                path
+            },
+            // Not having a config directory isn't a reason to refuse to run anything.
+            Err(_) => return Ok(None),
+        },
+    };
+
+    let mut prelude = String::new();
+    try!(fs::File::open(&path)
+        .and_then(|mut f| f.read_to_string(&mut prelude))
+        .map_err(|err| (Blame::Human, format!("cannot read prelude file `{}`: {}", path.display(), err))));
+    Ok(Some(prelude))
+}
+
+/**
+Joins several expressions into one, so that the last one is the result, and the others are run as statements before it.
+*/
+fn join_exprs(exprs: &[String]) -> String {
+    let mut joined = String::new();
+    if let Some((last, init)) = exprs.split_last() {
+        for expr in init {
+            joined.push_str(expr.trim_right().trim_right_matches(';'));
+            joined.push_str(";\n");
+        }
+        joined.push_str(last);
+    }
+    joined
+}
+
+#[test]
+fn test_join_exprs() {
+    let v = |exprs: &[&str]| exprs.iter().map(|&e| String::from(e)).collect::<Vec<_>>();
+    assert_eq!(join_exprs(&v(&["1 + 2"])), "1 + 2");
+    assert_eq!(join_exprs(&v(&["let x = 1", "x + 1"])), "let x = 1;\nx + 1");
+    assert_eq!(join_exprs(&v(&["let x = 1; ", "let y = 2;", "x + y"])), "let x = 1;\nlet y = 2;\nx + y");
+}
+
+/**
+Rewrites `script test SCRIPT` into `script --test SCRIPT` (and likewise for `bench`), so that it reads like the Cargo command it runs.
+
+This is only recognised directly after `script` (or a `+TOOLCHAIN` following it), and only if there's a script after it.  If there's a script called `test` (or `bench`) in the current directory, that's what the user means, and we leave it alone.
+*/
+fn expand_mode_arg(mut args: Vec<OsString>) -> Vec<OsString> {
+    if args.get(1).map(|a| a != "script").unwrap_or(true) {
+        return args;
+    }
+
+    let i = match args.get(2).and_then(|a| a.to_str()) {
+        Some(arg) if arg.starts_with("+") => 3,
+        _ => 2,
+    };
+    let flag = match args.get(i).and_then(|a| a.to_str()) {
+        Some("test") => "--test",
+        Some("bench") => "--bench",
+        _ => return args
+    };
+    if args.len() <= i + 1 || find_script(&args[i]).is_some() {
+        return args;
+    }
+
+    args[i] = flag.into();
+    args
+}
+
+#[test]
+fn test_expand_mode_arg() {
+    macro_rules! ema {
+        ($($args:expr),*) => (expand_mode_arg(vec![$(OsString::from($args)),*]))
+    }
+
+    macro_rules! osv {
+        ($($args:expr),*) => (vec![$(OsString::from($args)),*] as Vec<OsString>)
+    }
+
+    assert_eq!(ema!["cargo-script", "script", "test", "foo.rs"],
+        osv!["cargo-script", "script", "--test", "foo.rs"]);
+    assert_eq!(ema!["cargo-script", "script", "test", "foo.rs", "--", "--nocapture"],
+        osv!["cargo-script", "script", "--test", "foo.rs", "--", "--nocapture"]);
+    assert_eq!(ema!["cargo-script", "script", "+nightly", "test", "foo.rs"],
+        osv!["cargo-script", "script", "+nightly", "--test", "foo.rs"]);
+    assert_eq!(ema!["cargo-script", "script", "bench", "foo.rs"],
+        osv!["cargo-script", "script", "--bench", "foo.rs"]);
+    assert_eq!(ema!["cargo-script", "script", "test"],
+        osv!["cargo-script", "script", "test"]);
+    assert_eq!(ema!["cargo-script", "script", "foo.rs", "test"],
+        osv!["cargo-script", "script", "foo.rs", "test"]);
+    assert_eq!(ema!["cargo-script", "test", "foo.rs"],
+        osv!["cargo-script", "test", "foo.rs"]);
+}
+
+/**
+Rewrites a `+TOOLCHAIN` argument into `--toolchain TOOLCHAIN`.
+
+rustup users are used to writing `cargo +nightly build`.  If rustup's proxy sees `cargo +nightly script`, it handles the toolchain itself, but `cargo script +nightly` reaches us untouched.  `clap` has no way of expressing a "plus argument", so we do it by hand.  This is only recognised directly after `script`.
+*/
+fn expand_toolchain_arg(mut args: Vec<OsString>) -> Vec<OsString> {
+    let toolchain = match (args.get(1).and_then(|a| a.to_str()), args.get(2).and_then(|a| a.to_str())) {
+        (Some("script"), Some(arg)) if arg.starts_with("+") && arg.len() > 1 => arg[1..].to_owned(),
+        _ => return args
+    };
+
+    args[2] = "--toolchain".into();
+    args.insert(3, toolchain.into());
+    args
+}
+
+#[test]
+fn test_expand_toolchain_arg() {
+    macro_rules! eta {
+        ($($args:expr),*) => (expand_toolchain_arg(vec![$(OsString::from($args)),*]))
+    }
+
+    macro_rules! osv {
+        ($($args:expr),*) => (vec![$(OsString::from($args)),*] as Vec<OsString>)
+    }
+
+    assert_eq!(eta!["cargo-script", "script", "+nightly", "foo.rs"],
+        osv!["cargo-script", "script", "--toolchain", "nightly", "foo.rs"]);
+    assert_eq!(eta!["cargo-script", "script", "+1.20.0"],
+        osv!["cargo-script", "script", "--toolchain", "1.20.0"]);
+    assert_eq!(eta!["cargo-script", "script", "foo.rs", "+nightly"],
+        osv!["cargo-script", "script", "foo.rs", "+nightly"]);
+    assert_eq!(eta!["cargo-script", "script", "+"],
+        osv!["cargo-script", "script", "+"]);
+    assert_eq!(eta!["cargo-script", "templates", "+nightly"],
+        osv!["cargo-script", "templates", "+nightly"]);
+    assert_eq!(eta!["cargo-script"], osv!["cargo-script"]);
+}
+
+/**
+Turns a `--cfg` spec into the equivalent rustc flag.
+
+`name` is passed through as-is, whilst `name=value` has the value quoted, since that's what `#[cfg(name = "value")]` expects.  Because the result ends up in `RUSTFLAGS`, which is split on whitespace, neither part may contain any.
+*/
+fn cfg_rustc_flag(spec: &str) -> std::result::Result<String, String> {
+    let (name, value) = match spec.find('=') {
+        Some(i) => (&spec[..i], Some(spec[i+1..].trim_matches('"'))),
+        None => (spec, None),
+    };
+
+    let is_ident = name.chars().next().map(|c| c == '_' || c.is_alphabetic()).unwrap_or(false)
+        && name.chars().all(|c| c == '_' || c.is_alphanumeric());
+    if !is_ident {
+        return Err(format!("invalid cfg `{}`: expected `name` or `name=value`", spec));
+    }
+    if spec.chars().any(char::is_whitespace) || value.map(|v| v.contains('"')).unwrap_or(false) {
+        return Err(format!("invalid cfg `{}`: cannot contain whitespace or quotes", spec));
+    }
+
+    Ok(match value {
+        Some(value) => format!("--cfg {}=\"{}\"", name, value),
+        None => format!("--cfg {}", name),
+    })
+}
+
+/**
+Turns a lint name from `--deny`, `--warn`, or `--allow` into the equivalent rustc flag.
+
+Like `--cfg`s, these end up in `RUSTFLAGS`, so the name can't contain whitespace; it's restricted to the characters lint names are actually made of.
+*/
+fn lint_rustc_flag(flag: &str, lint: &str) -> std::result::Result<String, String> {
+    if lint == "" || !lint.chars().all(|c| c == '_' || c == '-' || c == ':' || c.is_alphanumeric()) {
+        return Err(format!("invalid lint `{}`: expected a lint name, such as `warnings` or `dead_code`", lint));
+    }
+    Ok(format!("{} {}", flag, lint))
+}
+
+#[test]
+fn test_lint_rustc_flag() {
+    assert_eq!(lint_rustc_flag("-D", "warnings"), Ok("-D warnings".into()));
+    assert_eq!(lint_rustc_flag("-A", "dead_code"), Ok("-A dead_code".into()));
+    assert_eq!(lint_rustc_flag("-W", "clippy::pedantic"), Ok("-W clippy::pedantic".into()));
+
+    assert!(lint_rustc_flag("-D", "").is_err());
+    assert!(lint_rustc_flag("-D", "dead_code -C opt-level=0").is_err());
+}
+
+/**
+Turns an `--extern-glob` path into the prelude items needed to import everything from it.
+*/
+fn extern_glob_items(path: &str) -> std::result::Result<Vec<String>, String> {
+    let valid = path.split("::")
+        .all(|seg| seg != "" && seg.chars().all(|c| c == '_' || c.is_alphanumeric()));
+    if !valid {
+        return Err(format!("invalid path `{}`: expected a crate name, optionally followed by `::module`s", path));
+    }
+
+    let krate = path.split("::").next().unwrap();
+    Ok(vec![
+        format!("#[macro_use] extern crate {};", krate),
+        format!("use {}::*;", path),
+    ])
+}
+
+#[test]
+fn test_extern_glob_items() {
+    assert_eq!(extern_glob_items("boolinator"), Ok(vec![
+        "#[macro_use] extern crate boolinator;".into(),
+        "use boolinator::*;".into(),
+    ]));
+    assert_eq!(extern_glob_items("itertools::prelude"), Ok(vec![
+        "#[macro_use] extern crate itertools;".into(),
+        "use itertools::prelude::*;".into(),
+    ]));
+
+    assert!(extern_glob_items("").is_err());
+    assert!(extern_glob_items("a::").is_err());
+    assert!(extern_glob_items("a::*").is_err());
+    assert!(extern_glob_items("a; b").is_err());
+}
+
+/**
+Checks that a string is a valid Rust identifier, and not a keyword.
+*/
+fn check_ident(s: &str) -> std::result::Result<(), &'static str> {
+    const KEYWORDS: &'static [&'static str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+    ];
+
+    let mut chars = s.chars();
+    match chars.next() {
+        None => return Err("it is empty"),
+        Some(c) if c == '_' || c.is_alphabetic() => (),
+        Some(_) => return Err("it must start with a letter or underscore"),
+    }
+    if !chars.all(|c| c == '_' || c.is_alphanumeric()) {
+        return Err("it may only contain letters, digits, and underscores");
+    }
+    if s == "_" {
+        return Err("`_` is not an identifier");
+    }
+    if KEYWORDS.contains(&s) {
+        return Err("it is a keyword");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_ident() {
+    assert_eq!(check_ident("mytool"), Ok(()));
+    assert_eq!(check_ident("_private2"), Ok(()));
+    assert_eq!(check_ident("My_Tool"), Ok(()));
+
+    assert!(check_ident("").is_err());
+    assert!(check_ident("_").is_err());
+    assert!(check_ident("2fast").is_err());
+    assert!(check_ident("my-tool").is_err());
+    assert!(check_ident("my tool").is_err());
+    assert!(check_ident("fn").is_err());
+}
+
+/**
+Splits a `KEY=VALUE` environment variable assignment at the first `=`, so the value may itself contain `=`.
+*/
+fn env_pair(spec: &str) -> std::result::Result<(String, String), String> {
+    match spec.find('=') {
+        Some(i) if i > 0 => Ok((spec[..i].into(), spec[i+1..].into())),
+        _ => Err(format!("invalid environment variable `{}`: expected `KEY=VALUE`", spec)),
+    }
+}
+
+#[test]
+fn test_env_pair() {
+    assert_eq!(env_pair("FOO=bar"), Ok(("FOO".into(), "bar".into())));
+    assert_eq!(env_pair("FOO="), Ok(("FOO".into(), "".into())));
+    assert_eq!(env_pair("FOO=a=b"), Ok(("FOO".into(), "a=b".into())));
+
+    assert!(env_pair("FOO").is_err());
+    assert!(env_pair("=bar").is_err());
+}
+
+#[test]
+fn test_cfg_rustc_flag() {
+    assert_eq!(cfg_rustc_flag("feature_x"), Ok("--cfg feature_x".into()));
+    assert_eq!(cfg_rustc_flag("mode=fast"), Ok("--cfg mode=\"fast\"".into()));
+    assert_eq!(cfg_rustc_flag("mode=\"fast\""), Ok("--cfg mode=\"fast\"".into()));
+    assert_eq!(cfg_rustc_flag("mode="), Ok("--cfg mode=\"\"".into()));
+
+    assert!(cfg_rustc_flag("").is_err());
+    assert!(cfg_rustc_flag("=fast").is_err());
+    assert!(cfg_rustc_flag("1x").is_err());
+    assert!(cfg_rustc_flag("a-b").is_err());
+    assert!(cfg_rustc_flag("mode=very fast").is_err());
+    assert!(cfg_rustc_flag("mode=a\"b").is_err());
+}
+
+/**
+Sets up logging.
+
+`RUST_LOG` always takes precedence.  Failing that, `--verbose` turns on our own informational logging, but *not* that of our dependencies.
+*/
+fn init_logging(verbose: bool) {
+    let mut builder = env_logger::LogBuilder::new();
+    match std::env::var("RUST_LOG") {
+        Ok(ref spec) => { builder.parse(spec); },
+        Err(_) if verbose => {
+            builder.filter(Some("cargo_script"), log::LogLevelFilter::Info);
+        },
+        Err(_) => (),
+    }
+    builder.init().unwrap();
+}
+
+/**
+Runs `cargo-script` using the process's own command-line arguments, returning the exit code it should finish with.
+
+This is what the `cargo-script` executable does; it's only public so that the executable can call it.
+*/
+#[doc(hidden)]
+pub fn try_main() -> Result<i32> {
+    // Logging depends on `--verbose`, so nothing before this point gets logged.
+    let (args, cargo_opts) = parse_args();
+    init_logging(cargo_opts.verbose);
+    info!("starting");
+    info!("args: {:?}", std::env::args().collect::<Vec<_>>());
+    info!("Arguments: {:?}", args);
+
+    match args {
+        SubCommand::Script(ref args) if args.interactive => interactive(args),
+        SubCommand::Script(args) => run(args).map(|ran| match ran {
+            Ran::Status(code) => code,
+            Ran::Output(_) => unreachable!("captured output without asking for it"),
+        }),
+        SubCommand::Doctor(args) => doctor::try_main(args),
+        SubCommand::Gc(args) => gc::try_main(args),
+        SubCommand::Migrate(args) => migrate::try_main(args),
+        SubCommand::Templates(args) => templates::try_main(args),
+        #[cfg(windows)]
+        SubCommand::FileAssoc(args) => file_assoc::try_main(args),
+    }
+}
+
+/**
+Evaluates expressions read from stdin, one per line, until it runs out.
+
+Every expression is built in the same package directory, so the dependencies stay built, and only the expression itself needs to be recompiled each time.  Failures are reported, but don't stop the loop.
+*/
+fn interactive(args: &Args) -> Result<i32> {
+    use std::io::BufRead;
+
+    let pkg_path = try!(interactive_pkg_path(args));
+    let pkg_path = pkg_path.to_string_lossy().into_owned();
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let _ = write!(std::io::stderr(), "> ");
+        let _ = std::io::stderr().flush();
+
+        let line = match lines.next() {
+            Some(line) => try!(line),
+            None => break,
+        };
+        let line = line.trim();
+        if line == "" {
+            continue;
+        }
+
+        let args = Args {
+            script: Some(line.into()),
+            expr: true,
+            interactive: false,
+            pkg_path: Some(pkg_path.clone()),
+            ..args.clone()
+        };
+        match run(args) {
+            Ok(Ran::Status(0)) => (),
+            Ok(Ran::Status(code)) => { let _ = writeln!(std::io::stderr(), "exited with status {}", code); },
+            Ok(Ran::Output(_)) => unreachable!("captured output without asking for it"),
+            Err(err) => { let _ = writeln!(std::io::stderr(), "error: {}", err); },
+        }
+    }
+
+    // Leave the terminal on a fresh line after the last prompt.
+    let _ = writeln!(std::io::stderr(), "");
+    Ok(0)
+}
+
+/**
+Works out which package directory an interactive session should use.
+
+Like `Input::compute_id`, this is keyed by the dependencies, along with everything else that goes into the generated manifest, so that sessions with different dependencies don't overwrite each other's package.
+*/
+fn interactive_pkg_path(args: &Args) -> Result<PathBuf> {
+    let mut key = String::new();
+    {
+        let mut add = |kind: &str, values: &[String]| {
+            for value in values {
+                key.push_str(kind);
+                key.push_str("=");
+                key.push_str(value);
+                key.push_str(";");
+            }
+        };
+        add("dep", &args.dep);
+        add("dep-extern", &args.dep_extern);
+        add("dep-features", &args.dep_features);
+        add("dep-no-default", &args.dep_no_default);
+        add("manifest", &args.manifest);
+        if let Some(edition) = args.edition.as_ref().or(args.default_edition.as_ref()) {
+            add("edition", &[edition.clone()]);
+        }
+        if args.output_format == OutputFormat::Json {
+            add("output-format", &["json".to_owned()]);
+        }
+    }
+
+    let mut digest = hash_str(&key);
+    digest.truncate(consts::ID_DIGEST_LEN_MAX);
+    Ok(try!(get_script_cache_path()).join(format!("interactive-{}", digest)))
+}
+
+/**
+Does the actual work of the `script` subcommand.
+*/
+fn run(args: Args) -> Result<Ran> {
+    /*
+    Do data migration before anything else, since it can cause the location of stuff to change.
+    */
+    if let Some(run_kind) = args.migrate_data {
+        try!(migrate::migrate(run_kind));
+        return Ok(Ran::Status(0));
+    }
+
+    if log_enabled!(log::LogLevel::Debug) {
+        let scp = try!(get_script_cache_path());
+        let bcp = try!(get_binary_cache_path());
+        debug!("script-cache path: {:?}", scp);
+        debug!("binary-cache path: {:?}", bcp);
+    }
+
+    if let Some(sort) = args.list_cache {
+        try!(list_cache(sort));
+        return Ok(Ran::Status(0));
+    }
+
+    /*
+    If we've been asked to clear the cache, do that *now*.  There are two reasons:
+
+    1. Do it *before* we call `decide_action_for` such that this flag *also* acts as a synonym for `--force`.
+    2. Do it *before* we start trying to read the input so that, later on, we can make `<script>` optional, but still supply `--clear-cache`.
+    */
+    if let Some(remove_kind) = args.clear_cache {
+        let (entries, bytes) = try!(clear_cache(remove_kind));
+
+        // If we *did not* get a `<script>` argument, that's OK.
+        if args.script.is_none() {
+            // Just let the user know that we did *actually* run.
+            if remove_kind.for_real() {
+                println!("cargo script cache cleared: removed {} entries, freeing {}.",
+                    entries, util::human_size(bytes));
+            } else {
+                println!("cargo script cache would be cleared: {} entries, {}.",
+                    entries, util::human_size(bytes));
+            }
+            return Ok(Ran::Status(0));
+        }
+    }
+
+    let args = try!(apply_config(args));
+
+    if let Some(ref name) = args.name {
+        try!(check_ident(name).map_err(|err| (Blame::Human, format!("invalid package name `{}`: {}", name, err))));
+    }
+    let name = args.name.as_ref().map(|s| &**s);
+
+    // Take the arguments and work out what our input is going to be.  Primarily, this gives us the content, a user-friendly name, and a cache-friendly ID.
+    // These three are just storage for the borrows we'll actually use.
+    let script_name: String;
+    let script_path: PathBuf;
+    let content: String;
+
+    let input = match (args.script, args.expr, args.loop_) {
+        (Some(ref script), false, false) if script == "-" => {
+            let body = match args.script_source {
+                Some(source) => source,
+                None => {
+                    let mut body = String::new();
+                    try!(std::io::stdin().read_to_string(&mut body));
+                    body
+                }
+            };
+
+            /*
+            Stash the script in a file named after its content, so that piping the same script in twice reuses the same cached package.  We *don't* rewrite an existing file, since that would change its modification time and force a rebuild.
+            */
+            let stdin_cache = try!(get_stdin_cache_path());
+            try!(fs::create_dir_all(&stdin_cache));
+
+            let mut digest = hash_str(&body);
+            digest.truncate(consts::ID_DIGEST_LEN_MAX);
+            let path = stdin_cache.join(format!("{}.rs", digest));
+            if !path.is_file_polyfill() {
+                try!(util::write_atomic(&path, |f| f.write_all(body.as_bytes())));
+            }
+
+            let file = try!(fs::File::open(&path));
+            let mtime = platform::file_last_modified(&file);
+
+            script_name = name.unwrap_or("stdin").into();
+            script_path = path;
+            content = body;
+
+            Input::File(&script_name, &script_path, &content, mtime, args.file || manifest::has_main_fn(&content))
+        },
+        (Some(script), false, false) => {
+            let (path, mut file) = try!(find_script(util::expand_tilde(&script)).ok_or("could not find script"));
+
+            script_name = match name {
+                Some(name) => name.into(),
+                None => path.file_stem()
+                    .map(|os| os.to_string_lossy().into_owned())
+                    .unwrap_or("unknown".into()),
+            };
+
+            let mut body = String::new();
+            try!(file.read_to_string(&mut body));
+
+            let mtime = platform::file_last_modified(&file);
+
+            script_path = try!(std::env::current_dir()).join(path);
+            content = body;
+
+            Input::File(&script_name, &script_path, &content, mtime, args.file || manifest::has_main_fn(&content))
+        },
+        (Some(expr), true, false) => {
+            content = expr;
+            Input::Expr(&content, args.template.as_ref().map(|s| &**s), name)
+        },
+        (Some(loop_), false, true) => {
+            content = loop_;
+            Input::Loop(&content, args.count, name)
+        },
+        (None, _, _) => try!(Err((Blame::Human, consts::NO_ARGS_MESSAGE))),
+        _ => try!(Err((Blame::Human,
+            "cannot specify both --expr and --loop")))
+    };
+    info!("input: {:?}", input);
+
+    // Sort out the dependencies: duplicates, features, and all.
+    let deps = {
+        let mut specs: Vec<&str> = args.dep.iter().chain(args.dep_extern.iter()).map(|s| &**s).collect();
+
+        // A bare name gives way to any version the user asked for.
+        if args.output_format == OutputFormat::Json {
+            specs.push("serde_json");
+        }
+
+        try!(manifest::merge_deps(&specs, &args.dep_features, &args.dep_no_default))
+    };
+    info!("deps: {:?}", deps);
+
+    /*
+    Generate the prelude items, if we need any.  Again, ensure consistent and *valid* sorting.
+    */
+    let prelude_items = {
+        let unstable_features = args.unstable_features.iter()
+            .map(|uf| format!("#![feature({})]", uf));
+        let dep_externs = args.dep_extern.iter()
+            .map(|d| match d.find('=') {
+                Some(i) => &d[..i],
+                None => &d[..]
+            })
+            .map(|d| match d.contains('-') {
+                true => Cow::from(d.replace("-", "_")),
+                false => Cow::from(d)
+            })
+            .map(|d| format!("#[macro_use] extern crate {};", d));
+
+        let externs = args.extern_.iter()
+            .map(|n| format!("#[macro_use] extern crate {};", n));
+
+        let extern_globs = args.extern_glob.iter()
+            .flat_map(|p| extern_glob_items(p).unwrap());
+
+        let output_externs = match args.output_format {
+            OutputFormat::Json => Some("#[macro_use] extern crate serde_json;".to_owned()),
+            _ => None,
+        };
+
+        let mut items: Vec<_> = unstable_features.chain(dep_externs).chain(externs).chain(extern_globs).chain(output_externs).collect();
+        items.sort();
+
+        // The same crate might have been asked for by more than one flag; declaring it twice won't compile.
+        items.dedup();
+
+        // The user's own prelude code goes last, in the order given, since it might depend on any of the above.
+        if args.expr || args.loop_ {
+            if let Some(prelude) = try!(read_prelude_file(args.prelude_file.as_ref().map(|s| &**s))) {
+                items.push(prelude);
+            }
+        }
+        items.extend(args.expr_prelude.iter().cloned());
+        items
+    };
+    info!("prelude_items: {:?}", prelude_items);
+
+    /*
+    Statements to run before the script itself.  The input file is only *read* by the script, but it's much friendlier to complain about it now than after a build.
+    */
+    let mut bindings = vec![];
+    let input_path = match args.input {
+        Some(ref path) => {
+            let path = try!(std::env::current_dir()).join(util::expand_tilde(path));
+            if !path.is_file() {
+                return Err((Blame::Human, format!("cannot read input file `{}`: it does not exist or is not a file", path.display())).into());
+            }
+            bindings.push(consts::INPUT_BINDING.to_owned());
+            Some(path)
+        },
+        None => None,
+    };
+    if let Some(ref var) = args.stdin_as {
+        let mut subs = std::collections::HashMap::new();
+        subs.insert("name", &**var);
+        bindings.push(try!(templates::expand(consts::STDIN_BINDING, &subs)));
+    }
+
+    // Work out what to do.
+    let mut action = try!(decide_action_for(&input, ActionOptions {
+        deps: deps,
+        manifest: args.manifest,
+        prelude: prelude_items,
+        bindings: bindings,
+        output: if args.exit_expr { Some(consts::EXIT_OUTPUT) } else { args.output_format.statement() },
+        debug: args.debug,
+        profile: args.profile,
+        bin: args.bin,
+        crate_type: args.crate_type,
+        strip: args.strip,
+        debug_symbols: args.debug_symbols,
+        pkg_path: args.pkg_path,
+        gen_pkg_only: args.gen_pkg_only || args.extract.is_some(),
+        build_only: args.build_only || args.print_binary_path || args.install,
+        force: args.force || args.update,
+        format: args.format,
+        dump_expanded: args.dump_expanded,
+        explain: args.explain_cache_decision,
+        json_messages: args.json_messages,
+        keep_pkg: args.keep_temp,
+        features: args.features,
+        all_features: args.all_features,
+        no_default_features: args.no_default_features,
+        edition: args.edition,
+        default_edition: args.default_edition,
+        rustc_flags: args.rustc_flags,
+        target: args.target,
+        toolchain: args.toolchain,
+        cargo_z: args.cargo_z,
+        use_bincache: args.use_bincache,
+        build_kind: args.build_kind,
+        cargo_opts: args.cargo_opts,
+    }));
+    info!("action: {:?}", action);
+
+    if args.warn_dirty_deps {
+        try!(warn_dirty_deps(&action.manifest));
+    }
+
+    if let Some(ref dir) = args.extract {
+        let dir = util::expand_tilde(dir);
+        try!(extract_pkg(&action, &dir, args.force));
+        let _ = writeln!(std::io::stderr(), "cargo script: extracted package to {}", dir.display());
+        return Ok(Ran::Status(0));
+    }
+
+    /*
+    To prime the cache, build the package with an empty `main` in place of the script.  The metadata has to describe what was *actually* built, or the next run would mistake the stub for the script.
+    */
+    if args.prime {
+        action.script = consts::PRIME_STUB.into();
+        action.metadata.script_hash = hash_str(&action.script);
+        action.body_line = None;
+        action.compile = true;
+        action.execute = false;
+    }
+
+    if action.keep_pkg {
+        let _ = writeln!(std::io::stderr(), "cargo script: generated package is in {}", action.pkg_path.display());
+    }
+
+    if args.explain_cache_decision {
+        let verdict = match (action.build_kind, action.compile, action.execute) {
+            (BuildKind::Test, _, _) | (BuildKind::Bench, _, _) | (BuildKind::Check, _, _) => "leave it to cargo",
+            (_, true, _) => "rebuild",
+            (_, false, true) => "reuse cached executable",
+            (_, false, false) => "nothing to build",
+        };
+        let _ = writeln!(std::io::stderr(), "cache: verdict: {}", verdict);
+    }
+
+    /*
+    Cargo keeps the lockfile next to the generated manifest, so the same dependency versions get used until it goes away.  Throwing it out is the simplest way to get fresh ones.
+    */
+    if args.update {
+        let lock_path = action.pkg_path.join("Cargo.lock");
+        if lock_path.is_file_polyfill() {
+            info!("removing {:?}", lock_path);
+            try!(fs::remove_file(&lock_path));
+        }
+    }
+
+    // Hooks get an absolute package path, since the command might `cd` somewhere else first.
+    let hook_pkg_path = try!(std::env::current_dir()).join(&action.pkg_path);
+
+    /*
+    Building and running are separate steps: `gen_pkg_and_compile` builds (if the action says to), and `execute_pkg` runs whatever is in the package directory.  With `--run-cached`, we skip the first step entirely, so there had better be something there already.
+    */
+    if args.run_cached {
+        if action.compile {
+            return Err((Blame::Human, "there is no up-to-date cached build of this script; run it without `--run-cached` to build it, or add `--explain-cache-decision` to see why the cached build can't be used").into());
+        }
+    } else {
+        if let Some(ref hook) = args.pre_build_hook {
+            info!("running pre-build hook: {:?}", hook);
+            let mut cmd = hook_command(hook, &input, &hook_pkg_path);
+            match platform::exit_status_code(try!(cmd.status())) {
+                0 => (),
+                n => return Err((Blame::Human, format!("pre-build hook `{}` failed with exit status {}", hook, n)).into()),
+            }
+        }
+
+        try!(gen_pkg_and_compile(&input, &action));
+    }
+
+    // Once we're done, clean out old packages from the cache.  There's no point if we've already done a full clear, though.
+    let _defer_clear = {
+        // To get around partially moved args problems.
+        let cc = args.clear_cache.is_some();
+        Defer::<_, MainError>::defer(move || {
+            if !cc {
+                try!(clean_cache(consts::MAX_CACHE_AGE_MS));
+            }
+            Ok(())
+        })
+    };
+
+    /*
+    If asked, tell the user where the executable is *instead* of running it.  Only the path goes to stdout, so this can be captured by a shell.
+
+    We also do this if it was built for another target, since we have no idea whether it can run here.
+    */
+    if args.install {
+        let exe_path = try!(get_exe_path(action.build_kind, &action.pkg_path));
+        let name = args.install_name.unwrap_or_else(|| input.package_name());
+        let dest = try!(install_exe(&exe_path, &name, args.install_dir, args.install_overwrite));
+        println!("Installed {}", dest.display());
+        return Ok(Ran::Status(0));
+    }
+
+    let print_binary_path = args.print_binary_path
+        || (action.execute && action.metadata.target.is_some() && action.build_kind.can_exec_directly());
+    if print_binary_path {
+        let exe_path = try!(get_exe_path(action.build_kind, &action.pkg_path));
+        let exe_path = try!(std::env::current_dir()).join(exe_path);
+        println!("{}", exe_path.display());
+        return Ok(Ran::Status(0));
+    }
+
+    // Run it!
+    let ran = if action.execute {
+        try!(execute_pkg(&input, &action, ExecOptions {
+            script_args: &args.args,
+            env: &args.env,
+            backtrace: args.backtrace.as_ref().map(|s| &**s),
+            input_path: input_path.as_ref().map(|p| &**p),
+            run_dir: args.run_dir.as_ref().map(|s| &**s),
+            capture_output: args.capture_output,
+            timeout: args.timeout,
+            output_marker: args.output_marker,
+        }))
+    } else {
+        // If nothing else failed, I suppose we succeeded.
+        Ran::Status(0)
+    };
+
+    /*
+    This is for cleaning up after the script, so it runs even if the script failed.  It failing shouldn't hide how the script itself went, though.
+    */
+    if let (true, Some(hook)) = (action.execute, args.post_run_hook.as_ref()) {
+        info!("running post-run hook: {:?}", hook);
+        let mut cmd = hook_command(hook, &input, &hook_pkg_path);
+        match platform::exit_status_code(try!(cmd.status())) {
+            0 => (),
+            n => { let _ = writeln!(std::io::stderr(), "warning: post-run hook `{}` failed with exit status {}", hook, n); },
+        }
+    }
+
+    Ok(ran)
+}
+
+/**
+Everything, other than the package itself, that goes into running a script.
+*/
+#[derive(Debug)]
+struct ExecOptions<'a> {
+    /// Arguments to pass to the script.
+    script_args: &'a [String],
+    /// Extra environment variables for the script.
+    env: &'a [(String, String)],
+    /// What to set `RUST_BACKTRACE` to, if anything.
+    backtrace: Option<&'a str>,
+    /// The file given with `--input`.
+    input_path: Option<&'a Path>,
+    /// Where to run the script, relative to the current directory.
+    run_dir: Option<&'a str>,
+    /// Capture the script's output, rather than letting it inherit ours.
+    capture_output: bool,
+    /// Kill the script after this many seconds.
+    timeout: Option<u64>,
+    /// Surround the script's output with marker lines.
+    output_marker: bool,
+}
+
+/**
+Runs an already-built package: either the executable directly, or, for tests and benchmarks, through Cargo.
+
+This never builds anything itself (beyond what `cargo test` or `cargo bench` decide to), so `gen_pkg_and_compile` must have been called first, on this or an earlier run.
+*/
+fn execute_pkg(input: &Input, action: &InputAction, opts: ExecOptions) -> Result<Ran> {
+    let ExecOptions {
+        script_args, env, backtrace, input_path, run_dir,
+        capture_output, timeout, output_marker,
+    } = opts;
+
+    fn hint<F: FnOnce(&mut Command) -> &mut Command>(f: F) -> F { f }
+    let add_env = hint(move |cmd| {
+        cmd.env("CARGO_SCRIPT_SCRIPT_PATH", input.path().unwrap_or(Path::new("")));
+        cmd.env("CARGO_SCRIPT_SAFE_NAME", input.safe_name());
+        cmd.env("CARGO_SCRIPT_PKG_NAME", input.package_name());
+        cmd.env("CARGO_SCRIPT_BASE_PATH", input.base_path());
+        if let Some(path) = input_path {
+            cmd.env("CARGO_SCRIPT_INPUT", path);
+        }
+        // An existing `RUST_BACKTRACE` is inherited like anything else; this just saves typing it.
+        if let Some(backtrace) = backtrace {
+            cmd.env("RUST_BACKTRACE", backtrace);
+        }
+        for &(ref k, ref v) in env {
+            cmd.env(k, v);
+        }
+        cmd
+    });
+
+    if action.build_kind.can_exec_directly() {
+        let cwd = try!(std::env::current_dir());
+        let exe_path = cwd.join(try!(get_exe_path(action.build_kind, &action.pkg_path)));
+
+        // The script runs wherever we were invoked from, unless told otherwise.
+        let run_dir = match run_dir {
+            Some(dir) => cwd.join(dir),
+            None => cwd,
+        };
+        if !run_dir.is_dir() {
+            return Err((Blame::Human, format!("cannot run script in `{}`: it is not a directory", run_dir.display())).into());
+        }
+
+        info!("executing {:?} in {:?}", exe_path, run_dir);
+        if capture_output {
+            let output = try!({
+                Command::new(exe_path)
+                    .args(script_args)
+                    .current_dir(&run_dir)
+                    .chain_map(add_env)
+                    .output()
+            });
+            Ok(Ran::Output(output))
+        } else {
+            let mut cmd = Command::new(exe_path);
+            cmd.args(script_args)
+                .current_dir(&run_dir)
+                .chain_map(add_env);
+            Ok(Ran::Status(try!(status_with_markers(&mut cmd, timeout, output_marker))))
+        }
+    } else {
+        let cmd_name = action.build_kind.exec_command();
+        info!("running `cargo {}`", cmd_name);
+        let mut cmd = try!(action.cargo(cmd_name));
+        if action.build_kind != BuildKind::Check && script_args.len() > 0 {
+            cmd.arg("--");
+            for arg in script_args {
+                cmd.arg(arg);
+            }
+        }
+        add_env(&mut cmd);
+        cmd.print();
+        Ok(Ran::Status(try!(status_with_markers(&mut cmd, timeout, output_marker)
+            .map_err(|err| match err {
+                MainError::Io(_, err) => cargo_spawn_error(err),
+                err => err,
+            }))))
+    }
+}
+
+/**
+Creates the command for a `--pre-build-hook` or `--post-run-hook`, which is run by the shell.
+*/
+fn hook_command(hook: &str, input: &Input, pkg_path: &Path) -> Command {
+    let mut cmd = platform::shell_command(hook);
+    cmd.env("CARGO_SCRIPT_SCRIPT_PATH", input.path().unwrap_or(Path::new("")));
+    cmd.env("CARGO_SCRIPT_PKG_PATH", pkg_path);
+    cmd.env("CARGO_SCRIPT_SAFE_NAME", input.safe_name());
+    cmd.env("CARGO_SCRIPT_PKG_NAME", input.package_name());
+    cmd
+}
+
+/**
+Runs a command to completion and returns its exit code.
+
+If `timeout` is given, the command (and anything it starts) is killed once that many seconds have passed.
+*/
+fn status_with_timeout(cmd: &mut Command, timeout: Option<u64>) -> Result<i32> {
+    let secs = match timeout {
+        Some(secs) => secs,
+        None => return Ok(platform::exit_status_code(try!(cmd.status()))),
+    };
+
+    match try!(platform::status_with_timeout(cmd, std::time::Duration::from_secs(secs))) {
+        Some(st) => Ok(platform::exit_status_code(st)),
+        None => Err(MainError::TimedOut(secs)),
+    }
+}
+
+/**
+Runs `cmd` as `status_with_timeout` does, but if `markers` is set, surrounds whatever it writes to standard output with marker lines.
+
+The end marker is written however the command finishes, including if it times out.
+*/
+fn status_with_markers(cmd: &mut Command, timeout: Option<u64>, markers: bool) -> Result<i32> {
+    if markers {
+        println!("{}", consts::OUTPUT_BEGIN_MARKER);
+    }
+    let status = status_with_timeout(cmd, timeout);
+    if markers {
+        println!("{}", consts::OUTPUT_END_MARKER);
+    }
+    status
+}
+
+/**
+Builds the text shown by `--version`: our own version, followed by the details worth including in a bug report.
+*/
+fn version_info(version: &str) -> String {
+    fn show<E: std::fmt::Display>(r: std::result::Result<PathBuf, E>) -> String {
+        match r {
+            Ok(path) => path.display().to_string(),
+            Err(err) => format!("unknown ({})", err),
+        }
+    }
+
+    format!("{}\nbuilt with: {}\nscripts use: {}\ncache dir: {}\nconfig dir: {}",
+        version,
+        option_env!("CARGO_SCRIPT_BUILD_RUSTC").unwrap_or("unknown"),
+        rustc_version(None).unwrap_or("unknown".into()),
+        show(platform::get_cache_dir()),
+        show(platform::get_config_dir()))
+}
+
+/**
+Fills in anything not given on the command line from the global `config.toml` and the nearest `.cargo-script.toml`.
+
+For script files, the search for a project config starts in the script's directory; otherwise, it starts in the current directory.
+*/
+fn apply_config(mut args: Args) -> Result<Args> {
+    let cwd = try!(std::env::current_dir());
+    let start = match args.script {
+        Some(ref script) if !args.expr && !args.loop_ && script != "-" => {
+            match Path::new(script).parent() {
+                Some(dir) => cwd.join(dir),
+                None => cwd,
+            }
+        },
+        _ => cwd,
+    };
+
+    let config = try!(config::load(&start));
+    info!("config: {:?}", config);
+
+    // Unlike `--edition`, this gives way to an edition in the script's own manifest.
+    args.default_edition = config.edition;
+    if args.template.is_none() {
+        args.template = config.template;
+    }
+    if !args.debug && !args.release && args.profile.is_none() {
+        args.debug = config.debug.unwrap_or(false);
+    }
+
+    // Dependencies given on the command line replace any of the same name from the config.
+    let mut deps = config.dependencies;
+    if args.expr || args.loop_ {
+        deps = config::merge_deps(deps, config.expr_dependencies);
+    }
+    let cli_deps = args.dep.iter().chain(&args.dep_extern).cloned().collect();
+    let deps = config::merge_deps(deps, cli_deps);
+    args.dep = deps.into_iter()
+        .filter(|d| !args.dep_extern.contains(d))
+        .collect();
+
+    if config.offline == Some(true) {
+        args.cargo_opts.offline = true;
+    }
+
+    Ok(args)
+}
+
+/**
+Clear out the script, binary, and standard input caches.
+
+This removes everything *inside* the cache directories, but leaves the directories themselves alone.  Returns the number of entries removed, and roughly how many bytes they took up.
+*/
+fn clear_cache(kind: RemoveKind) -> Result<(usize, u64)> {
+    info!("clearing cache ({:?})", kind);
+
+    let mut entries = 0;
+    let mut bytes = 0;
+
+    for cache_dir in &[try!(get_script_cache_path()), try!(get_binary_cache_path()), try!(get_stdin_cache_path())] {
+        if !cache_dir.is_dir() {
+            info!("{:?} does not exist; skipping", cache_dir);
+            continue;
+        }
+
+        for child in try!(fs::read_dir(cache_dir)) {
+            let child = try!(child);
+            let path = child.path();
+            let is_dir = try!(child.file_type()).is_dir();
+
+            entries += 1;
+            bytes += util::disk_usage(&path).unwrap_or(0);
+
+            if !kind.for_real() {
+                info!("would remove {:?}", path);
+                continue;
+            }
+
+            info!("removing {:?}", path);
+            if ALLOW_AUTO_REMOVE {
+                let res = if is_dir {
+                    fs::remove_dir_all(&path)
+                } else {
+                    fs::remove_file(&path)
+                };
+                if let Err(err) = res {
+                    error!("failed to remove {:?} from cache: {}", path, err);
+                }
+            } else {
+                info!("(suppressed remove)");
+            }
+        }
+    }
+
+    // The index only describes what we just removed.
+    let index_path = try!(cache_index::get_index_path());
+    if kind.for_real() && ALLOW_AUTO_REMOVE && index_path.exists() {
+        info!("removing {:?}", index_path);
+        if let Err(err) = fs::remove_file(&index_path) {
+            error!("failed to remove {:?}: {}", index_path, err);
+        }
+    }
+
+    info!("done clearing cache.");
+    Ok((entries, bytes))
+}
+
+/**
+Lists the packages in the script cache: what each was built from, roughly how much space it and its executable take up, and how long ago it was built.
+*/
+fn list_cache(sort: CacheSort) -> Result<()> {
+    struct Entry {
+        name: String,
+        source: String,
+        bytes: u64,
+        modified: u64,
+    }
+
+    let cache_dir = try!(get_script_cache_path());
+    let index = try!(cache_index::load());
+    let mut entries = vec![];
+
+    if cache_dir.is_dir() {
+        for child in try!(fs::read_dir(&cache_dir)) {
+            let child = try!(child);
+            if !try!(child.file_type()).is_dir() {
+                continue;
+            }
+            let path = child.path();
+
+            // Read the metadata loosely, so that packages built by older versions still show up properly.
+            let meta = fs::File::open(get_pkg_metadata_path(&path)).ok()
+                .and_then(|mut f| rustc_serialize::json::Json::from_reader(&mut f).ok());
+            let field = |name| meta.as_ref()
+                .and_then(|meta| meta.find(name))
+                .and_then(|v| v.as_string())
+                .map(String::from);
+            let name = child.file_name().to_string_lossy().into_owned();
+            let source = match (meta.is_some(), field("path"), field("template")) {
+                (_, Some(script_path), _) => script_path,
+                (_, None, Some(template)) => format!("(expression, template `{}`)", template),
+                (true, None, None) => "(expression)".into(),
+                (false, _, _) => index.get(&name)
+                    .map(|e| e.source.clone())
+                    .unwrap_or("(unknown)".into()),
+            };
+
+            let mut bytes = util::disk_usage(&path).unwrap_or(0);
+            if let Ok(exe_path) = get_exe_path(BuildKind::Normal, &path) {
+                bytes += util::disk_usage(&exe_path).unwrap_or(0);
+            }
+
+            let modified = fs::File::open(get_pkg_metadata_path(&path))
+                .map(|f| platform::file_last_modified(&f))
+                .unwrap_or(0);
+
+            entries.push(Entry {
+                name: name,
+                source: source,
+                bytes: bytes,
+                modified: modified,
+            });
+        }
+    }
+
+    match sort {
+        CacheSort::Name => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+        CacheSort::Size => entries.sort_by(|a, b| b.bytes.cmp(&a.bytes)),
+        CacheSort::Age => entries.sort_by(|a, b| a.modified.cmp(&b.modified)),
+    }
+
+    let now = platform::current_time();
+    for entry in &entries {
+        println!("{:>10}  {:>4}  {}  {}",
+            util::human_size(entry.bytes),
+            util::human_age(now.saturating_sub(entry.modified)),
+            entry.name,
+            entry.source);
+    }
+
+    let total = util::disk_usage(&try!(get_binary_cache_path())).unwrap_or(0);
+    println!("cargo script cache: {} entries; binary cache is {} in total.",
+        entries.len(), util::human_size(total));
+
+    Ok(())
+}
+
+/**
+Clean up the cache folder.
+
+Looks for all folders whose metadata says they were created at least `max_age` in the past and kills them dead.
+*/
+fn clean_cache(max_age: u64) -> Result<()> {
+    info!("cleaning cache with max_age: {:?}", max_age);
+
+    let cutoff = platform::current_time() - max_age;
+    info!("cutoff:     {:>20?} ms", cutoff);
+
+    let cache_dir = try!(get_script_cache_path());
+    for child in try!(fs::read_dir(cache_dir)) {
+        let child = try!(child);
+        let path = child.path();
+        if path.is_file_polyfill() { continue }
+
+        info!("checking: {:?}", path);
+
+        let remove_dir = || {
+            /*
+            Ok, so *why* aren't we using `modified in the package metadata?  The point of *that* is to track what we know about the input.  The problem here is that `--expr` and `--loop` don't *have* modification times; they just *are*.
+
+            Now, `PackageMetadata` *could* be modified to store, say, the moment in time the input was compiled, but then we couldn't use that field for metadata matching when decided whether or not a *file* input should be recompiled.
+
+            So, instead, we're just going to go by the timestamp on the metadata file *itself*.
+            */
+            let meta_mtime = {
+                let meta_path = get_pkg_metadata_path(&path);
+                let meta_file = match fs::File::open(&meta_path) {
+                    Ok(file) => file,
+                    Err(..) => {
+                        info!("couldn't open metadata for {:?}", path);
+                        return true
+                    }
+                };
+                platform::file_last_modified(&meta_file)
+            };
+            info!("meta_mtime: {:>20?} ms", meta_mtime);
+
+            (meta_mtime <= cutoff)
+        };
+
+        if remove_dir() {
+            info!("removing {:?}", path);
+            if ALLOW_AUTO_REMOVE {
+                if let Err(err) = fs::remove_dir_all(&path) {
+                    error!("failed to remove {:?} from cache: {}", path, err);
+                }
+            } else {
+                info!("(suppressed remove)");
+            }
+        }
+    }
+    info!("done cleaning cache.");
+    Ok(())
+}
+
+/**
+Generate and compile a package from the input.
+
+Why take `PackageMetadata`?  To ensure that any information we need to depend on for compilation *first* passes through `decide_action_for` *and* is less likely to not be serialised with the rest of the metadata.
+*/
+fn gen_pkg_and_compile(
+    input: &Input,
+    action: &InputAction,
+) -> Result<()> {
+    let pkg_path = &action.pkg_path;
+    let meta = &action.metadata;
+    let old_meta = action.old_metadata.as_ref();
+
+    let mani_str = &action.manifest;
+    let script_str = &action.script;
+
+    info!("creating pkg dir...");
+    try!(fs::create_dir_all(pkg_path));
+    let cleanup_dir: Defer<_, MainError> = Defer::defer(|| {
+        // DO NOT try deleting ANYTHING if we're not cleaning up inside our own cache.  We *DO NOT* want to risk killing user files.
+        if action.using_cache && !action.keep_pkg {
+            info!("cleaning up cache directory {:?}", pkg_path);
+            if ALLOW_AUTO_REMOVE {
+                try!(fs::remove_dir_all(pkg_path));
+            } else {
+                info!("(suppressed remove)");
+            }
+        }
+        Ok(())
+    });
+
+    let mut meta = meta.clone();
+
+    info!("generating Cargo package...");
+    let mani_path = {
+        let mani_path = action.manifest_path();
+        let mani_hash = old_meta.map(|m| &*m.manifest_hash);
+        match try!(overwrite_file(&mani_path, mani_str, mani_hash)) {
+            FileOverwrite::Same => (),
+            FileOverwrite::Changed { new_hash } => {
+                meta.manifest_hash = new_hash;
+            },
+        }
+        mani_path
+    };
+
+    {
+        let script_path = pkg_path.join(format!("{}.rs", input.safe_name()));
+        /*
+        There are times (particularly involving shared target dirs) where we can't rely on Cargo to correctly detect invalidated builds.  As such, if we've been told to *force* a recompile, we'll deliberately force the script to be overwritten, which will invalidate the timestamp, which will lead to a recompile.
+        */
+        let script_hash = if action.force_compile {
+            debug!("told to force compile, ignoring script hash");
+            None
+        } else {
+            old_meta.map(|m| &*m.script_hash)
+        };
+        match try!(overwrite_file(&script_path, script_str, script_hash)) {
+            FileOverwrite::Same => (),
+            FileOverwrite::Changed { new_hash } => {
+                meta.script_hash = new_hash;
+            },
+        }
+    }
+
+    let meta = meta;
+
+    /*
+    *bursts through wall* It's Cargo Time! (Possibly)
+
+    Note that there's a complication here: we want to *temporarily* continue *even if compilation fails*.  This is because if we don't, then every time you run `cargo script` on a script you're currently modifying, and it fails to compile, your compiled dependencies get obliterated.
+
+    This is *really* annoying.
+
+    As such, we want to ignore any compilation problems until *after* we've written the metadata and disarmed the cleanup callback.
+    */
+    let mut compile_err = Ok(());
+    let mut exe_path = None;
+    if action.compile {
+        info!("compiling...");
+
+        /*
+        If the toolchain we were asked for isn't installed, rustup's complaint would be buried in the middle of a failed build.  Better to find out now.
+        */
+        if let Some(ref toolchain) = meta.toolchain {
+            try!(cargo_version(Some(toolchain)));
+        }
+
+        let mut cmd = try!(cargo("build", &*mani_path.to_string_lossy(), action.use_bincache, &meta, &action.cargo_opts));
+        if action.json_messages {
+            cmd.arg("--message-format=json");
+        }
+
+        #[cfg(feature="suppress-cargo-output")]
+        macro_rules! get_status {
+            ($cmd:expr) => {
+                // `try!` doesn't work here on <=1.12.
+                (match util::suppress_child_output(
+                    &mut $cmd,
+                    ::std::time::Duration::from_millis(CARGO_OUTPUT_TIMEOUT)
+                ) {
+                    Ok(v) => v,
+                    Err(e) => return Err(e),
+                }).status()
+            }
+        }
+
+        #[cfg(not(feature="suppress-cargo-output"))]
+        macro_rules! get_status {
+            ($cmd:expr) => {
+                $cmd.status()
+            }
+        }
+
+        cmd.print();
+        compile_err = get_status!(cmd).map_err(cargo_spawn_error)
+            .and_then(|st|
+                match st.code() {
+                    Some(0) => Ok(()),
+                    code => Err(MainError::BuildFailed(code)),
+                });
+
+        /*
+        Any diagnostics will have been reported against the expanded source, not what the user actually wrote.  We can't rewrite them, but we *can* tell the user how to line them up.
+        */
+        if let (&Err(MainError::BuildFailed(_)), Some(line)) = (&compile_err, action.body_line) {
+            let script_name = format!("{}.rs", input.safe_name());
+            let _ = writeln!(std::io::stderr(), "note: your code was wrapped in a template before being compiled; it starts on line {} of `{}`", line, script_name);
+            // Packages outside the cache are never cleaned up, so they're always there to look at.
+            if action.keep_pkg || !action.using_cache {
+                let _ = writeln!(std::io::stderr(), "note: the expanded source is in {}", pkg_path.join(&script_name).display());
+            } else {
+                let _ = writeln!(std::io::stderr(), "note: use `--dump-expanded` to see the expanded source, or `--keep-temp` to keep it");
+            }
+        }
+
+        // Drop out now if compilation failed.
+        let _ = try!(compile_err);
+
+        // Find out and cache what the executable was called.
+        exe_path = Some(try!(cargo_target(input, pkg_path, &*mani_path.to_string_lossy(), action.use_bincache, &meta, &action.cargo_opts)));
+
+        if action.use_bincache {
+            // Write out the metadata hash to tie this executable to a particular chunk of metadata.  This is to avoid issues with multiple scripts with the same name being compiled to a common target directory.
+            let meta_hash = action.metadata.sha1_hash();
+            info!("writing meta hash: {:?}...", meta_hash);
+            let exe_meta_hash_path = try!(get_meta_hash_path(action.use_bincache, exe_path.as_ref().unwrap()));
+            try!(util::write_atomic(&exe_meta_hash_path, |f| write!(f, "{}", meta_hash)));
+        }
+    }
+
+    // Write out metadata *now*.  Remember that we check the timestamp in the metadata, *not* on the executable.
+    if action.emit_metadata {
+        info!("emitting metadata...");
+        try!(write_pkg_metadata(pkg_path, &meta));
+    }
+
+    // Note where this package came from, so cache tooling can show something better than a hash.
+    if action.using_cache && action.compile {
+        if let Err(err) = index_pkg(input, pkg_path, &meta, exe_path.as_ref().map(|p| &**p)) {
+            error!("failed to update cache index: {}", err);
+        }
+    }
+
+    info!("disarming pkg dir cleanup...");
+    cleanup_dir.disarm();
+
+    compile_err
+}
+
+/**
+Writes the generated package out to `dir` as an ordinary Cargo project, with the source in `src/main.rs` rather than next to the manifest.
+
+Unless `force` is set, this refuses to replace any existing files.
+*/
+fn extract_pkg(action: &InputAction, dir: &Path, force: bool) -> Result<()> {
+    let mani_path = dir.join("Cargo.toml");
+    let main_path = dir.join("src").join("main.rs");
+
+    if !force {
+        for path in &[&mani_path, &main_path] {
+            if path.exists() {
+                return Err((Blame::Human, format!("cannot extract package: {} already exists; use `--force` to replace it", path.display())).into());
+            }
+        }
+    }
+
+    let mani_str = try!(manifest::set_bin_path(&action.manifest, "src/main.rs"));
+
+    info!("extracting package to {:?}...", dir);
+    try!(fs::create_dir_all(dir.join("src")));
+    try!(util::write_atomic(&mani_path, |f| f.write_all(mani_str.as_bytes())));
+    try!(util::write_atomic(&main_path, |f| f.write_all(action.script.as_bytes())));
+    Ok(())
+}
+
+/**
+Records a freshly built package in the cache index.
+*/
+fn index_pkg(input: &Input, pkg_path: &Path, meta: &PackageMetadata, exe_path: Option<&Path>) -> Result<()> {
+    let name = match pkg_path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err(format!("package path {:?} has no name", pkg_path).into()),
+    };
+
+    let source = match *input {
+        Input::File(_, path, _, _, _) => path.to_string_lossy().into_owned(),
+        Input::Expr(_, Some(template), _) => format!("(expression, template `{}`)", template),
+        Input::Expr(_, None, _) => "(expression)".into(),
+        Input::Loop(..) => "(loop)".into(),
+    };
+
+    let entry = cache_index::Entry {
+        source: source,
+        created: platform::current_time(),
+        profile: match meta.profile {
+            Some(ref profile) => profile.clone(),
+            None if meta.debug => "debug".into(),
+            None => "release".into(),
+        },
+        exe: exe_path.map(|p| p.to_string_lossy().into_owned()),
+    };
+
+    let script_cache = try!(get_script_cache_path());
+    cache_index::record(&script_cache, &name, entry)
+}
+
+/**
+This represents what to do with the input provided by the user.
+*/
+#[derive(Debug)]
+struct InputAction {
+    /// Compile the input into a fresh executable?
+    compile: bool,
+
+    /**
+    Force Cargo to do a recompile, even if it thinks it doesn't have to.
+
+    `compile` must be `true` for this to have any effect.
+    */
+    force_compile: bool,
+
+    /// Emit a metadata file?
+    emit_metadata: bool,
+
+    /// Execute the compiled binary?
+    execute: bool,
+
+    /// Directory where the package should live.
+    pkg_path: PathBuf,
+
+    /**
+    Is the package directory in the cache?
+
+    Currently, this can be inferred from `emit_metadata`, but there's no *intrinsic* reason they should be tied together.
+    */
+    using_cache: bool,
+
+    /// Use shared binary cache?
+    use_bincache: bool,
+
+    /// The package metadata structure for the current invocation.
+    metadata: PackageMetadata,
+
+    /// The package metadata structure for the *previous* invocation, if it exists.
+    old_metadata: Option<PackageMetadata>,
+
+    /// The package manifest contents.
+    manifest: String,
+
+    /// The script source.
+    script: String,
+
+    /// Line of `script` on which the user's code starts, if it was wrapped in a template.
+    body_line: Option<usize>,
+
+    /// Did the user ask to run tests or benchmarks?
+    build_kind: BuildKind,
+
+    /// Have Cargo write its messages as JSON to standard output?
+    json_messages: bool,
+
+    /// Keep the package directory, even if the build fails?
+    keep_pkg: bool,
+
+    /// How to run Cargo.
+    cargo_opts: CargoOptions,
+}
+
+impl InputAction {
+    fn manifest_path(&self) -> PathBuf {
+        self.pkg_path.join("Cargo.toml")
+    }
+
+    fn cargo(&self, cmd: &str) -> Result<CargoCmd> {
+        let mut cmd = try!(cargo(cmd, &*self.manifest_path().to_string_lossy(), self.use_bincache, &self.metadata, &self.cargo_opts));
+        if self.json_messages {
+            cmd.arg("--message-format=json");
+        }
+        Ok(cmd)
+    }
+}
+
+/**
+The metadata here serves two purposes:
+
+1. It records everything necessary for compilation and execution of a package.
+2. It records everything that must be exactly the same in order for a cached executable to still be valid, in addition to the content hash.
+*/
+#[derive(Clone, Debug, Eq, PartialEq, RustcDecodable, RustcEncodable)]
+struct PackageMetadata {
+    /// Path to the script file.
+    path: Option<String>,
+
+    /// Last-modified timestamp for script file.
+    modified: Option<u64>,
+
+    /// Template used.
+    template: Option<String>,
+
+    /// Was the script compiled in debug mode?
+    debug: bool,
+
+    /// Cargo profile the script was built with, if one was given explicitly.
+    profile: Option<String>,
+
+    /// Binary target to build and run, if it isn't named after the package.
+    bin: Option<String>,
+
+    /// Crate type to build the script as, if it's a library rather than an executable.
+    crate_type: Option<String>,
+
+    /// Strip symbols from the executable?
+    strip: bool,
+
+    /// Keep debug symbols in a release build?
+    debug_symbols: bool,
+
+    /// Sorted list of dependencies.
+    deps: Vec<(String, String)>,
+
+    /// Sorted list of injected prelude items.
+    prelude: Vec<String>,
+
+    /// Cargo features
+    features: Option<String>,
+
+    /// Enable all features?
+    all_features: bool,
+
+    /// Disable default features?
+    no_default_features: bool,
+
+    /// Extra flags to pass to rustc.
+    rustc_flags: Vec<String>,
+
+    /// Target triple to build for, if not the host.
+    target: Option<String>,
+
+    /// rustup toolchain to build with, if not the default.
+    toolchain: Option<String>,
+
+    /// Version of the compiler, as reported by `rustc -V`.
+    rustc_version: Option<String>,
+
+    /// Unstable `-Z` flags to pass to Cargo.
+    cargo_z: Vec<String>,
+
+    /// Hash of the generated `Cargo.toml` file.
+    manifest_hash: String,
+
+    /// Hash of the generated source file.
+    script_hash: String,
+}
+
+impl PackageMetadata {
+    pub fn sha1_hash(&self) -> String {
+        // Yes, I *do* feel dirty for doing it like this.  :D
+        hash_str(&format!("{:?}", self))
+    }
+}
+
+/**
+Everything, other than the input itself, that goes into deciding what to do with it.
+*/
+#[derive(Debug)]
+struct ActionOptions<'a> {
+    /// Dependencies, already merged and sorted.
+    deps: Vec<(String, String)>,
+    /// Fragments of TOML to merge into the manifest.
+    manifest: Vec<String>,
+    /// Items to put before a wrapped expression or loop.
+    prelude: Vec<String>,
+    /// Statements to run before the script's own code.
+    bindings: Vec<String>,
+    /// What to do with an expression's value, if not the default.
+    output: Option<&'a str>,
+    debug: bool,
+    profile: Option<String>,
+    bin: Option<String>,
+    crate_type: Option<String>,
+    strip: bool,
+    debug_symbols: bool,
+    /// Where to generate the package, instead of the cache.
+    pkg_path: Option<String>,
+    gen_pkg_only: bool,
+    build_only: bool,
+    force: bool,
+    format: bool,
+    dump_expanded: bool,
+    /// Explain the cache decision on standard error?
+    explain: bool,
+    json_messages: bool,
+    keep_pkg: bool,
+    features: Option<String>,
+    all_features: bool,
+    no_default_features: bool,
+    edition: Option<String>,
+    /// Edition to use if neither `edition` nor the script's manifest gives one.
+    default_edition: Option<String>,
+    rustc_flags: Vec<String>,
+    target: Option<String>,
+    toolchain: Option<String>,
+    cargo_z: Vec<String>,
+    use_bincache: Option<bool>,
+    build_kind: BuildKind,
+    cargo_opts: CargoOptions,
+}
+
+/**
+For the given input, this constructs the package metadata and checks the cache to see what should be done.
+*/
+fn decide_action_for(
+    input: &Input,
+    opts: ActionOptions,
+) -> Result<InputAction> {
+    let ActionOptions {
+        deps, manifest, prelude, bindings, output,
+        debug, profile, bin, crate_type, strip, debug_symbols,
+        pkg_path, gen_pkg_only, build_only, force,
+        format, dump_expanded, explain, json_messages, keep_pkg,
+        features, all_features, no_default_features, edition, default_edition,
+        rustc_flags, target, toolchain, cargo_z, use_bincache,
+        build_kind, cargo_opts,
+    } = opts;
+
+    let (pkg_path, using_cache) = match pkg_path {
+        Some(p) => (p.into(), false),
+        None => {
+            // This can fail if the user has overridden the cache location with something unusable.
+            let cache_path = try!(get_script_cache_path());
+            info!("cache_path: {:?}", cache_path);
+
+            let id = {
+                let deps_iter = deps.iter()
+                    .map(|&(ref n, ref v)| (n as &str, v as &str));
+
+                // This can't fail.  Seriously, we're *fucked* if we can't work this out.
+                input.compute_id(deps_iter).unwrap()
+            };
+            info!("id: {:?}", id);
+
+            (cache_path.join(&id), true)
+        },
+    };
+    info!("pkg_path: {:?}", pkg_path);
+    info!("using_cache: {:?}", using_cache);
+
+    info!("splitting input...");
+    let (mani_str, script_str, body_line) = try!(manifest::split_input(input, &deps, &manifest, &prelude, &bindings, output, edition.as_ref().map(|s| &**s), default_edition.as_ref().map(|s| &**s)));
+
+    /*
+    Formatting happens *before* we hash anything, so that the metadata reflects what's actually on disk.  It doesn't affect the package ID, though.
+    */
+    let (script_str, body_line) = if format {
+        match rustfmt(&script_str) {
+            // rustfmt is free to move things around, so we can't say where the user's code ended up any more.
+            Ok(formatted) => (formatted, None),
+            Err(err) => {
+                let _ = writeln!(std::io::stderr(), "warning: not formatting source: {}", err);
+                (script_str, body_line)
+            }
+        }
+    } else {
+        (script_str, body_line)
+    };
+
+    // This is what actually gets compiled, so show it *after* any formatting.
+    if dump_expanded {
+        let _ = writeln!(std::io::stderr(), "{}", script_str);
+    }
+
+    let mani_str = match crate_type {
+        Some(ref crate_type) => try!(manifest::set_lib_target(&mani_str, crate_type)),
+        None => mani_str,
+    };
+
+    /*
+    A script's manifest can replace the binary target we generate with several of its own.  Cargo would build them all, but we can only run one, and we shouldn't guess which.
+    */
+    let bin = {
+        let bins = try!(manifest::bin_names(&mani_str));
+        match bin {
+            Some(bin) => {
+                if !bins.contains(&bin) {
+                    return Err((Blame::Human, format!("the script has no binary named `{}`; it has: {}", bin, bins.join(", "))).into());
+                }
+                Some(bin)
+            },
+            None if bins.len() > 1 => {
+                return Err((Blame::Human, format!("the script has more than one binary, so use `--bin` to pick which to run: {}", bins.join(", "))).into());
+            },
+            None => match bins.into_iter().next() {
+                Some(ref bin) if *bin == input.package_name() => None,
+                bin => bin,
+            },
+        }
+    };
+
+    /*
+    Feature flags only make sense for scripts that actually *have* features.  Cargo would just complain about missing features otherwise, so we drop them with a warning instead.
+    */
+    let (features, all_features, no_default_features) = {
+        let any = features.is_some() || all_features || no_default_features;
+        if any && !manifest::declares_features(&mani_str) {
+            let _ = writeln!(std::io::stderr(), "warning: ignoring feature flags: the script does not declare any features");
+            (None, false, false)
+        } else {
+            (features, all_features, no_default_features)
+        }
+    };
+
+    /*
+    Stable Cargo refuses to accept `-Z` flags at all, so there's no point passing them on unless we're on nightly.
+    */
+    let cargo_z = if cargo_z.len() > 0 && !try!(cargo_is_nightly(toolchain.as_ref().map(|s| &**s))) {
+        let _ = writeln!(std::io::stderr(), "warning: ignoring -Z flags: they require a nightly toolchain");
+        vec![]
+    } else {
+        cargo_z
+    };
+
+    // Forcibly override some flags based on build kind.
+    let (debug, force, build_only) = match build_kind {
+        BuildKind::Normal => (debug, force, build_only),
+        BuildKind::Test => (true, false, false),
+        BuildKind::Bench => (false, false, false),
+        BuildKind::Check => (debug, false, false),
+    };
+
+    // Construct input metadata.
+    let input_meta = {
+        let (path, mtime, template) = match *input {
+            Input::File(_, path, _, mtime, _)
+                => (Some(path.to_string_lossy().into_owned()), Some(mtime), None),
+            Input::Expr(_, template, _)
+                => (None, None, template),
+            Input::Loop(..)
+                => (None, None, None)
+        };
+        PackageMetadata {
+            path: path,
+            modified: mtime,
+            template: template.map(Into::into),
+            debug: debug,
+            profile: profile,
+            bin: bin,
+            crate_type: crate_type,
+            strip: strip,
+            debug_symbols: debug_symbols,
+            deps: deps,
+            prelude: prelude,
+            features: features,
+            all_features: all_features,
+            no_default_features: no_default_features,
+            rustc_flags: rustc_flags,
+            target: target,
+            rustc_version: rustc_version(toolchain.as_ref().map(|s| &**s)),
+            toolchain: toolchain,
+            cargo_z: cargo_z,
+            manifest_hash: hash_str(&mani_str),
+            script_hash: hash_str(&script_str),
+        }
+    };
+    info!("input_meta: {:?}", input_meta);
+
+    // Lazy powers, ACTIVATE!
+    let mut action = InputAction {
+        compile: force,
+        force_compile: force,
+        emit_metadata: true,
+        execute: !build_only,
+        pkg_path: pkg_path,
+        using_cache: using_cache,
+        use_bincache: use_bincache.unwrap_or(using_cache),
+        metadata: input_meta,
+        old_metadata: None,
+        manifest: mani_str,
+        script: script_str,
+        body_line: body_line,
+        build_kind: build_kind,
+        json_messages: json_messages,
+        keep_pkg: keep_pkg,
+        cargo_opts: cargo_opts,
+    };
+
+    /*
+    Log the reasoning behind the decision.  If the user asked us to explain ourselves, they get to see it too, whether or not logging is turned on.
+    */
+    macro_rules! decision {
+        ($($args:tt)*) => {
+            {
+                info!($($args)*);
+                if explain {
+                    let _ = writeln!(std::io::stderr(), "cache: {}", format_args!($($args)*));
+                }
+            }
+        }
+    }
+
+    macro_rules! bail {
+        ($($name:ident: $value:expr),*) => {
+            return Ok(InputAction {
+                $($name: $value,)*
+                ..action
+            })
+        }
+    }
+
+    decision!("package path: {:?}", action.pkg_path);
+    if force {
+        decision!("recompiling because: user asked for a forced rebuild");
+    }
+
+    // If we were told to only generate the package, we need to stop *now*
+    if gen_pkg_only {
+        bail!(compile: false, execute: false)
+    }
+
+    // If we're not doing a regular build, stop.
+    match action.build_kind {
+        BuildKind::Normal => (),
+        BuildKind::Test | BuildKind::Bench | BuildKind::Check => {
+            decision!("not recompiling because: user asked for test/bench/check");
+            bail!(compile: false, force_compile: false)
+        }
+    }
+
+    let cache_meta = match get_pkg_metadata(&action.pkg_path) {
+        Ok(meta) => meta,
+        Err(err) => {
+            decision!("recompiling because: failed to load metadata");
+            debug!("get_pkg_metadata error: {}", err.description());
+            bail!(compile: true)
+        }
+    };
+
+    if cache_meta != action.metadata {
+        decision!("recompiling because: metadata did not match");
+        debug!("input metadata: {:?}", action.metadata);
+        debug!("cache metadata: {:?}", cache_meta);
+
+        {
+            let (old, new) = (&cache_meta, &action.metadata);
+            if old.path != new.path {
+                decision!("- script path changed: {:?} -> {:?}", old.path, new.path);
+            }
+            if old.modified != new.modified {
+                decision!("- script modified time changed: {:?} ms -> {:?} ms", old.modified, new.modified);
+            }
+            if old.template != new.template {
+                decision!("- template changed: {:?} -> {:?}", old.template, new.template);
+            }
+            if old.debug != new.debug {
+                decision!("- debug changed: {:?} -> {:?}", old.debug, new.debug);
+            }
+            if old.profile != new.profile {
+                decision!("- profile changed: {:?} -> {:?}", old.profile, new.profile);
+            }
+            if old.bin != new.bin {
+                decision!("- bin changed: {:?} -> {:?}", old.bin, new.bin);
+            }
+            if old.crate_type != new.crate_type {
+                decision!("- crate type changed: {:?} -> {:?}", old.crate_type, new.crate_type);
+            }
+            if old.strip != new.strip {
+                decision!("- strip changed: {:?} -> {:?}", old.strip, new.strip);
+            }
+            if old.debug_symbols != new.debug_symbols {
+                decision!("- debug symbols changed: {:?} -> {:?}", old.debug_symbols, new.debug_symbols);
+            }
+            if old.deps != new.deps {
+                decision!("- dependencies changed: {:?} -> {:?}", old.deps, new.deps);
+            }
+            if old.prelude != new.prelude {
+                decision!("- prelude items changed: {:?} -> {:?}", old.prelude, new.prelude);
+            }
+            if old.features != new.features {
+                decision!("- features changed: {:?} -> {:?}", old.features, new.features);
+            }
+            if old.all_features != new.all_features {
+                decision!("- all features changed: {:?} -> {:?}", old.all_features, new.all_features);
+            }
+            if old.no_default_features != new.no_default_features {
+                decision!("- no default features changed: {:?} -> {:?}", old.no_default_features, new.no_default_features);
+            }
+            if old.rustc_flags != new.rustc_flags {
+                decision!("- rustc flags changed: {:?} -> {:?}", old.rustc_flags, new.rustc_flags);
+            }
+            if old.target != new.target {
+                decision!("- target changed: {:?} -> {:?}", old.target, new.target);
+            }
+            if old.toolchain != new.toolchain {
+                decision!("- toolchain changed: {:?} -> {:?}", old.toolchain, new.toolchain);
+            }
+            if old.rustc_version != new.rustc_version {
+                decision!("- rustc version changed: {:?} -> {:?}", old.rustc_version, new.rustc_version);
+            }
+            if old.cargo_z != new.cargo_z {
+                decision!("- -Z flags changed: {:?} -> {:?}", old.cargo_z, new.cargo_z);
+            }
+            if old.manifest_hash != new.manifest_hash {
+                decision!("- manifest hash changed: {} -> {}", old.manifest_hash, new.manifest_hash);
+            }
+            if old.script_hash != new.script_hash {
+                decision!("- script hash changed: {} -> {}", old.script_hash, new.script_hash);
+            }
+        }
+
+        bail!(old_metadata: Some(cache_meta), compile: true)
+    }
+
+    decision!("metadata matches cached package");
+    action.old_metadata = Some(cache_meta);
+
+    /*
+    Next test: does the executable exist at all?
+    */
+    let exe_path = match get_exe_path(action.build_kind, &action.pkg_path) {
+        Ok(exe_path) => if exe_path.is_file_polyfill() { Some(exe_path) } else { None },
+        Err(_) => None,
+    };
+    if let Some(ref exe_path) = exe_path {
+        decision!("found cached executable: {:?}", exe_path);
+        if explain {
+            let exe_mtime = fs::File::open(exe_path).ok()
+                .map(|f| platform::file_last_modified(&f));
+            decision!("script modified: {:?} ms, executable modified: {:?} ms",
+                action.metadata.modified, exe_mtime);
+        }
+    } else {
+        decision!("recompiling because: executable doesn't exist or isn't a file");
+        bail!(compile: true)
+    }
+
+    /*
+    Finally: check to see if `{exe_path}.meta-hash` exists and contains a hash that matches the metadata.  Yes, this is somewhat round-about, but we need to do this to account for cases where Cargo's target directory has been set to a fixed, shared location.
+
+    Note that we *do not* do this if we aren't using the cache.
+    */
+    if action.use_bincache {
+        let exe_meta_hash_path = get_meta_hash_path(action.use_bincache, exe_path.as_ref().unwrap()).unwrap();
+        if !exe_meta_hash_path.is_file_polyfill() {
+            decision!("recompiling because: meta hash doesn't exist or isn't a file");
+            bail!(compile: true, force_compile: true)
+        }
+        let exe_meta_hash = {
+            let mut f = try!(fs::File::open(&exe_meta_hash_path));
+            let mut s = String::new();
+            try!(f.read_to_string(&mut s));
+            s
+        };
+        let meta_hash = action.metadata.sha1_hash();
+        if meta_hash != exe_meta_hash {
+            decision!("recompiling because: meta hash doesn't match");
+            bail!(compile: true, force_compile: true)
+        }
+    }
+
+    // That's enough; let's just go with it.
+    Ok(action)
+}
+
+/**
+Figures out where the output executable for the input should be.
+
+This *requires* that `cargo_target` has already been called on the package.
+*/
+fn get_exe_path<P>(build_kind: BuildKind, pkg_path: P) -> Result<PathBuf>
+where P: AsRef<Path> {
+    use std::fs::File;
+
+    // We don't directly run tests and benchmarks.
+    match build_kind {
+        BuildKind::Normal => (),
+        BuildKind::Test | BuildKind::Bench | BuildKind::Check => {
+            return Err("tried to get executable path for test/bench build".into());
+        },
+    }
+
+    let package_path = pkg_path.as_ref();
+    let cache_path = package_path.join("target.exe_path");
+
+    let mut f = try!(File::open(&cache_path));
+    let exe_path = try!(platform::read_path(&mut f));
+
+    Ok(exe_path)
+}
+
+/**
+Warns about any path dependencies with uncommitted changes, according to `git status`.
+
+Dependencies that aren't in a git repository (or if git can't be run at all) are left alone; there's nothing useful to say about them.
+*/
+fn warn_dirty_deps(manifest: &str) -> Result<()> {
+    for (name, path) in try!(manifest::path_deps(manifest)) {
+        let out = Command::new("git")
+            .args(&["status", "--porcelain", "--", "."])
+            .current_dir(&path)
+            .output();
+        match out {
+            Ok(ref out) if out.status.success() => {
+                if out.stdout.len() > 0 {
+                    let _ = writeln!(std::io::stderr(), "warning: path dependency `{}` has uncommitted changes, so this build may not be reproducible: {}", name, path);
+                }
+            },
+            Ok(out) => info!("not checking {:?} for changes: git exited with {}", path, out.status),
+            Err(err) => info!("not checking {:?} for changes: {}", path, err),
+        }
+    }
+    Ok(())
+}
+
+/**
+Copies a compiled script's executable to `dir` (or Cargo's `bin` directory) as `name`.
+
+Returns the path it was copied to.
+*/
+fn install_exe(exe_path: &Path, name: &str, dir: Option<String>, overwrite: bool) -> Result<PathBuf> {
+    let dir = match dir {
+        Some(dir) => PathBuf::from(dir),
+        None => try!(get_cargo_bin_path()),
+    };
+    try!(fs::create_dir_all(&dir));
+
+    let dest = dir.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+    if dest.exists() && !overwrite {
+        return Err((Blame::Human, format!("`{}` already exists; use --overwrite to replace it", dest.display())).into());
+    }
+
+    info!("installing {:?} to {:?}", exe_path, dest);
+    try!(fs::copy(exe_path, &dest));
+    Ok(dest)
+}
+
+/**
+Figures out where `cargo install` puts executables: `$CARGO_HOME/bin`, or `~/.cargo/bin`.
+*/
+fn get_cargo_bin_path() -> Result<PathBuf> {
+    if let Some(home) = std::env::var_os("CARGO_HOME") {
+        return Ok(PathBuf::from(home).join("bin"));
+    }
+
+    match std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+        Some(home) => Ok(Path::new(&home).join(".cargo").join("bin")),
+        None => Err((Blame::Human, "cannot work out where to install to; use --install-dir").into()),
+    }
+}
+
+/**
+Figures out where the `meta-hash` file should be.
+
+This lives next to the executable, not in the package, since the binary cache is shared: another script with the same name can overwrite the executable without touching this package.
+*/
+fn get_meta_hash_path<P>(use_bincache: bool, exe_path: P) -> Result<PathBuf>
+where P: AsRef<Path> {
+    if !use_bincache {
+        panic!("tried to get meta-hash path when not using binary cache");
+    }
+    let mut path = exe_path.as_ref().as_os_str().to_owned();
+    path.push(".meta-hash");
+    Ok(PathBuf::from(path))
+}
+
+/**
+Load the package metadata, given the path to the package's cache folder.
+*/
+fn get_pkg_metadata<P>(pkg_path: P) -> Result<PackageMetadata>
+where P: AsRef<Path> {
+    let meta_path = get_pkg_metadata_path(pkg_path);
+    debug!("meta_path: {:?}", meta_path);
+    let mut meta_file = try!(fs::File::open(&meta_path));
+
+    let meta_str = {
+        let mut s = String::new();
+        meta_file.read_to_string(&mut s).unwrap();
+        s
+    };
+    let meta: PackageMetadata = try!(rustc_serialize::json::decode(&meta_str)
+        .map_err(|err| err.to_string()));
+
+    Ok(meta)
+}
+
+/**
+Work out the path to a package's metadata file.
+*/
+fn get_pkg_metadata_path<P>(pkg_path: P) -> PathBuf
+where P: AsRef<Path> {
+    pkg_path.as_ref().join(consts::METADATA_FILE)
+}
+
+/**
+Save the package metadata, given the path to the package's cache folder.
+*/
+fn write_pkg_metadata<P>(pkg_path: P, meta: &PackageMetadata) -> Result<()>
+where P: AsRef<Path> {
+    let meta_path = get_pkg_metadata_path(pkg_path);
+    debug!("meta_path: {:?}", meta_path);
+    let meta_str = try!(rustc_serialize::json::encode(meta)
+        .map_err(|err| err.to_string()));
+    try!(util::write_atomic(&meta_path, |f| f.write_all(meta_str.as_bytes())));
+    Ok(())
+}
+
+/**
+Returns the path to the cache directory.
+*/
+fn get_script_cache_path() -> Result<PathBuf> {
+    let cache_path = try!(platform::get_cache_dir());
+    Ok(cache_path.join("script-cache"))
+}
+
+/**
+Returns the path to the binary cache directory.
+*/
+fn get_binary_cache_path() -> Result<PathBuf> {
+    let cache_path = try!(platform::get_cache_dir());
+    Ok(cache_path.join("binary-cache"))
+}
+
+/**
+Returns the target directory a package should be built into when using the shared binary cache.
+
+Cargo already keeps profiles apart (`debug` and `release`), and packages apart by name.  What it *doesn't* do well is switch between toolchains: dependencies built by one are useless to another, and the final executable is simply overwritten.  So scripts built with an explicit toolchain get their own target directory.
+*/
+fn get_shared_target_path(meta: &PackageMetadata) -> Result<PathBuf> {
+    let mut target_path = try!(get_binary_cache_path());
+    if let Some(ref toolchain) = meta.toolchain {
+        target_path.push(toolchain_dir_name(toolchain));
+    }
+    Ok(target_path)
+}
+
+/**
+Turns a toolchain name into something safe to use as a directory name.
+*/
+fn toolchain_dir_name(toolchain: &str) -> String {
+    let mut r = String::from("toolchain-");
+    for c in toolchain.chars() {
+        match c {
+            '0'...'9' | 'a'...'z' | 'A'...'Z' | '-' | '_' | '.' => r.push(c),
+            _ => r.push('_')
+        }
+    }
+    r
+}
+
+#[test]
+fn test_toolchain_dir_name() {
+    assert_eq!(toolchain_dir_name("nightly"), "toolchain-nightly");
+    assert_eq!(toolchain_dir_name("1.20.0"), "toolchain-1.20.0");
+    assert_eq!(toolchain_dir_name("nightly-2017-10-01-x86_64-pc-windows-msvc"), "toolchain-nightly-2017-10-01-x86_64-pc-windows-msvc");
+    assert_eq!(toolchain_dir_name("/opt/rust/my toolchain"), "toolchain-_opt_rust_my_toolchain");
+}
+
+/**
+Returns the path to the directory where scripts read from standard input are kept.
+*/
+fn get_stdin_cache_path() -> Result<PathBuf> {
+    let cache_path = try!(platform::get_cache_dir());
+    Ok(cache_path.join("stdin-cache"))
+}
+
+/**
+Attempts to locate the script specified by the given path.  If the path as-given doesn't yield anything, it will try adding file extensions.
+*/
+fn find_script<P>(path: P) -> Option<(PathBuf, fs::File)>
+where P: AsRef<Path> {
+    let path = path.as_ref();
+
+    // Try the path directly.
+    if let Ok(file) = fs::File::open(path) {
+        return Some((path.into(), file));
+    }
+
+    // If it had an extension, don't bother trying any others.
+    if path.extension().is_some() {
+        return None;
+    }
+
+    // Ok, now try other extensions.
+    for &ext in consts::SEARCH_EXTS {
+        let path = path.with_extension(ext);
+        if let Ok(file) = fs::File::open(&path) {
+            return Some((path, file));
+        }
+    }
+
+    // Welp. ¯\_(ツ)_/¯
+    None
+}
+
+/**
+Represents an input source for a script.
+*/
+#[derive(Clone, Debug)]
+pub enum Input<'a> {
+    /**
+    The input is a script file.
+
+    The tuple members are: the name, absolute path, script contents, last modified time, and whether it's a complete program (as opposed to a bare expression).
+    */
+    File(&'a str, &'a Path, &'a str, u64, bool),
+
+    /**
+    The input is an expression.
+
+    The tuple members are: the script contents, the template (if any), and the name to use instead of `expr` (if any).
+    */
+    Expr(&'a str, Option<&'a str>, Option<&'a str>),
+
+    /**
+    The input is a loop expression.
+
+    The tuple members are: the script contents, whether the `--count` flag was given, and the name to use instead of `loop` (if any).
+    */
+    Loop(&'a str, bool, Option<&'a str>),
+}
+
+impl<'a> Input<'a> {
+    /**
+    Return the path to the script, if it has one.
+    */
+    pub fn path(&self) -> Option<&Path> {
+        use Input::*;
+
+        match *self {
+            File(_, path, _, _, _) => Some(path),
+            Expr(..) => None,
+            Loop(..) => None,
+        }
+    }
+
+    /**
+    Return the "safe name" for the input.  This should be filename-safe.
+
+    Currently, nothing is done to ensure this, other than hoping *really hard* that we don't get fed some excessively bizzare input filename.
+    */
+    pub fn safe_name(&self) -> &str {
+        use Input::*;
+
+        match *self {
+            File(name, _, _, _, _) => name,
+            Expr(_, _, name) => name.unwrap_or("expr"),
+            Loop(_, _, name) => name.unwrap_or("loop"),
+        }
+    }
+
+    /**
+    Return the package name for the input.  This should be a valid Rust identifier.
+    */
+    pub fn package_name(&self) -> String {
+        let name = self.safe_name();
+        let mut r = String::with_capacity(name.len());
+
+        for (i, c) in name.chars().enumerate() {
+            match (i, c) {
+                (0, '0'...'9') => {
+                    r.push('_');
+                    r.push(c);
+                },
+                (_, '0'...'9')
+                | (_, 'a'...'z')
+                | (_, 'A'...'Z')
+                | (_, '_')
+                | (_, '-')
+                => {
+                    r.push(c);
+                },
+                (_, _) => {
+                    r.push('_');
+                }
+            }
+        }
+
+        r
+    }
+
+    /**
+    Base directory for resolving relative paths.
+    */
+    pub fn base_path(&self) -> PathBuf {
+        match *self {
+            Input::File(_, path, _, _, _) => path.parent().expect("couldn't get parent directory for file input base path").into(),
+            Input::Expr(..) | Input::Loop(..) => std::env::current_dir().expect("couldn't get current directory for input base path"),
+        }
+    }
+
+    /**
+    Compute the package ID for the input.  This is used as the name of the cache folder into which the Cargo package will be generated.
+    */
+    pub fn compute_id<'dep, DepIt>(&self, deps: DepIt) -> Result<OsString>
+    where DepIt: IntoIterator<Item=(&'dep str, &'dep str)> {
+        use shaman::digest::Digest;
+        use shaman::sha1::Sha1;
+        use Input::*;
+
+        let hash_deps = || {
+            let mut hasher = Sha1::new();
+            for dep in deps {
+                hasher.input_str("dep=");
+                hasher.input_str(dep.0);
+                hasher.input_str("=");
+                hasher.input_str(dep.1);
+                hasher.input_str(";");
+            }
+            hasher
+        };
+
+        match *self {
+            File(name, path, _, _, _) => {
+                let mut hasher = Sha1::new();
+
+                // Hash the path to the script.
+                hasher.input_str(&path.to_string_lossy());
+                let mut digest = hasher.result_str();
+                digest.truncate(consts::ID_DIGEST_LEN_MAX);
+
+                let mut id = OsString::new();
+                id.push("file-");
+                id.push(name);
+                id.push("-");
+                id.push(if STUB_HASHES { "stub" } else { &*digest });
+                Ok(id)
+            },
+            Expr(content, template, name) => {
+                let mut hasher = hash_deps();
+
+                hasher.input_str("template:");
+                hasher.input_str(template.unwrap_or(""));
+                hasher.input_str(";");
+
+                if let Some(name) = name {
+                    hasher.input_str("name:");
+                    hasher.input_str(name);
+                    hasher.input_str(";");
+                }
+
+                hasher.input_str(&content);
+                let mut digest = hasher.result_str();
+                digest.truncate(consts::ID_DIGEST_LEN_MAX);
+
+                let mut id = OsString::new();
+                id.push("expr-");
+                id.push(if STUB_HASHES { "stub" } else { &*digest });
+                Ok(id)
+            },
+            Loop(content, count, name) => {
+                let mut hasher = hash_deps();
+
+                // Make sure to include the [non-]presence of the `--count` flag in the flag, since it changes the actual generated script output.
+                hasher.input_str("count:");
+                hasher.input_str(if count { "true;" } else { "false;" });
+
+                if let Some(name) = name {
+                    hasher.input_str("name:");
+                    hasher.input_str(name);
+                    hasher.input_str(";");
+                }
+
+                hasher.input_str(&content);
+                let mut digest = hasher.result_str();
+                digest.truncate(consts::ID_DIGEST_LEN_MAX);
+
+                let mut id = OsString::new();
+                id.push("loop-");
+                id.push(if STUB_HASHES { "stub" } else { &*digest });
+                Ok(id)
+            },
+        }
+    }
+}
+
+/**
+Shorthand for hashing a string.
+*/
+fn hash_str(s: &str) -> String {
+    use shaman::digest::Digest;
+    use shaman::sha1::Sha1;
+    let mut hasher = Sha1::new();
+    hasher.input_str(s);
+    hasher.result_str()
+}
+
+enum FileOverwrite {
+    Same,
+    Changed { new_hash: String },
+}
+
+/**
+Overwrite a file if and only if the contents have changed.
+*/
+fn overwrite_file<P>(path: P, content: &str, hash: Option<&str>) -> Result<FileOverwrite>
+where P: AsRef<Path> {
+    debug!("overwrite_file({:?}, _, {:?})", path.as_ref(), hash);
+    let new_hash = hash_str(content);
+    if Some(&*new_hash) == hash {
+        debug!(".. hashes match");
+        return Ok(FileOverwrite::Same);
+    }
+
+    debug!(".. hashes differ; new_hash: {:?}", new_hash);
+    try!(util::write_atomic(path.as_ref(), |f| f.write_all(content.as_bytes())));
+    Ok(FileOverwrite::Changed { new_hash: new_hash })
+}
+
+/**
+Constructs a Cargo command that runs on the script package.
+*/
+fn cargo(cmd_name: &str, manifest: &str, use_bincache: bool, meta: &PackageMetadata, opts: &CargoOptions) -> Result<CargoCmd> {
+    let mut cmd = CargoCmd::new(opts.print_cmd);
+    if let Some(ref toolchain) = meta.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+
+    /*
+    Cargo splits `RUSTFLAGS` on whitespace, which is exactly what we want for things like `-C link-arg=-s`.  Anything the user *already* had in there is kept.
+    */
+    if meta.rustc_flags.len() > 0 {
+        let mut rustflags = std::env::var("RUSTFLAGS").unwrap_or(String::new());
+        for flag in &meta.rustc_flags {
+            if rustflags != "" {
+                rustflags.push(' ');
+            }
+            rustflags.push_str(flag);
+        }
+        cmd.env("RUSTFLAGS", rustflags);
+    }
+    cmd.arg(cmd_name)
+        .arg("--manifest-path").arg(manifest);
+
+    /*
+    These go through Cargo's profile environment variables, rather than the manifest, so that they apply on top of whatever the script's own manifest says.
+    */
+    let profile = match meta.profile {
+        Some(ref profile) => profile.to_uppercase().replace("-", "_"),
+        None if meta.debug => "DEV".into(),
+        None => "RELEASE".into(),
+    };
+    if meta.strip {
+        cmd.env(format!("CARGO_PROFILE_{}_STRIP", profile), "true");
+    }
+    if meta.debug_symbols {
+        cmd.env(format!("CARGO_PROFILE_{}_DEBUG", profile), "true");
+    }
+
+    for flag in &meta.cargo_z {
+        cmd.arg("-Z").arg(flag);
+    }
+
+    if let Some(color) = try!(platform::cargo_color(opts.color.as_ref().map(|s| &**s))) {
+        cmd.arg("--color").arg(color);
+    }
+
+    if opts.quiet {
+        cmd.arg("--quiet");
+    } else if opts.verbose {
+        cmd.arg("--verbose");
+    }
+
+    if use_bincache {
+        cmd.env("CARGO_TARGET_DIR", try!(get_shared_target_path(meta)));
+    }
+
+    /*
+    A compiler wrapper only changes how fast things build, not what gets built, so it isn't part of the cache key.  If we weren't given one, Cargo still sees any `RUSTC_WRAPPER` the user has set.
+    */
+    if let Some(ref wrapper) = opts.rustc_wrapper {
+        cmd.env("RUSTC_WRAPPER", wrapper);
+    }
+
+    /*
+    Like the wrapper, this doesn't change what gets built.  Cargo keeps incremental state in the target directory, so it lives in the cache alongside the script's build (and is cleaned up by `gc` along with everything else).
+    */
+    if let Some(incremental) = opts.incremental {
+        cmd.env("CARGO_INCREMENTAL", if incremental { "1" } else { "0" });
+    }
+
+    if let Some(ref profile) = meta.profile {
+        lazy_static! {
+            static ref VER_PROFILE: Version = Version::parse("1.57.0").unwrap();
+        }
+
+        let cargo_ver = try!(cargo_version(meta.toolchain.as_ref().map(|s| &**s))
+            .err_tag("could not determine whether cargo supports --profile"));
+        if cargo_ver < *VER_PROFILE {
+            return Err((Blame::Human, format!("cannot build with profile `{}`: cargo {} does not support --profile (1.57.0 or later is needed)", profile, cargo_ver)).into());
+        }
+        cmd.arg("--profile").arg(profile);
+    } else if !meta.debug && cmd_name != "bench" {
+        // Block `--release` on `bench`.
+        cmd.arg("--release");
+    }
+
+    if let Some(ref bin) = meta.bin {
+        cmd.arg("--bin").arg(bin);
+    }
+
+    if let Some(ref features) = meta.features {
+        cmd.arg("--features").arg(features);
+    }
+
+    if meta.all_features {
+        cmd.arg("--all-features");
+    }
+
+    if meta.no_default_features {
+        cmd.arg("--no-default-features");
+    }
+
+    if let Some(ref target) = meta.target {
+        cmd.arg("--target").arg(target);
+    }
+
+    if opts.locked {
+        cmd.arg("--locked");
+    }
+
+    if opts.offline {
+        lazy_static! {
+            static ref VER_OFFLINE: Version = Version::parse("1.36.0").unwrap();
+        }
+
+        let cargo_ver = try!(cargo_version(meta.toolchain.as_ref().map(|s| &**s))
+            .err_tag("could not determine whether cargo supports --offline"));
+        if cargo_ver < *VER_OFFLINE {
+            return Err((Blame::Human, format!("cannot build offline: cargo {} does not support --offline (1.36.0 or later is needed)", cargo_ver)).into());
+        }
+        cmd.arg("--offline");
+    }
+
+    Ok(cmd)
+}
+
+/**
+A Cargo command, along with a record of the arguments and environment variables it's been given.
+
+`Command` can't tell us what's been set on it, so this keeps track, in order that `--print-cargo-cmd` can show exactly what's being run.  Anything set through the `Command` itself isn't recorded.
+*/
+struct CargoCmd {
+    cmd: Command,
+    args: Vec<OsString>,
+    env: Vec<(String, OsString)>,
+    print: bool,
+}
+
+impl CargoCmd {
+    fn new(print: bool) -> CargoCmd {
+        CargoCmd {
+            cmd: Command::new("cargo"),
+            args: vec![],
+            env: vec![],
+            print: print,
+        }
+    }
+
+    fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut CargoCmd {
+        self.args.push(arg.as_ref().to_os_string());
+        self.cmd.arg(arg);
+        self
+    }
+
+    fn env<K: Into<String>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut CargoCmd {
+        let key = key.into();
+        self.cmd.env(&key, &val);
+        self.env.push((key, val.as_ref().to_os_string()));
+        self
+    }
+
+    /**
+    Shows the command on standard error, if we've been asked to.  This should be called just before it's run.
+    */
+    fn print(&self) {
+        if !self.print {
+            return;
+        }
+
+        let mut line = String::new();
+        for &(ref key, ref val) in &self.env {
+            if cfg!(windows) {
+                // Without the quotes, `cmd.exe` would include the space before `&&` in the value.
+                line.push_str(&format!("set \"{}={}\" && ", key, val.to_string_lossy()));
+            } else {
+                line.push_str(&format!("{}={} ", key, util::shell_quote(&val.to_string_lossy())));
+            }
+        }
+        line.push_str("cargo");
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&util::shell_quote(&arg.to_string_lossy()));
+        }
+        let _ = writeln!(std::io::stderr(), "cargo script: running: {}", line);
+    }
+}
+
+impl std::ops::Deref for CargoCmd {
+    type Target = Command;
+
+    fn deref(&self) -> &Command {
+        &self.cmd
+    }
+}
+
+impl std::ops::DerefMut for CargoCmd {
+    fn deref_mut(&mut self) -> &mut Command {
+        &mut self.cmd
+    }
+}
+
+/**
+Is the given environment variable set to something other than nothing or `0`?
+
+This is how global switches like `--offline` can be turned on from the environment.
+*/
+fn env_flag(name: &str) -> bool {
+    match std::env::var(name) {
+        Ok(v) => v != "" && v != "0",
+        Err(_) => false,
+    }
+}
+
+/**
+Tries to find the path to a package's target file.
+
+This will also cache this information such that `exe_path` can find it later.
+*/
+fn cargo_target<P>(input: &Input, pkg_path: P, manifest: &str, use_bincache: bool, meta: &PackageMetadata, opts: &CargoOptions) -> Result<PathBuf>
+where P: AsRef<Path> {
+    lazy_static! {
+        static ref VER_JSON_MSGS: Version = Version::parse("0.18.0").unwrap();
+    }
+
+    trace!("cargo_target(_, {:?}, {:?}, {:?}, _)", pkg_path.as_ref(), manifest, use_bincache);
+
+    let cargo_ver = try!(cargo_version(meta.toolchain.as_ref().map(|s| &**s))
+        .err_tag("could not determine target filename"));
+
+    let mut use_guess = false;
+    use_guess |= work_around_issue_50();
+    use_guess |= if cargo_ver < *VER_JSON_MSGS {
+        trace!(".. cargo {:?} is too old to support JSON output", cargo_ver);
+        true
+    } else {
+        false
+    };
+
+    let exe_path = if use_guess {
+        try!(cargo_target_by_guess(input, use_bincache, pkg_path.as_ref(), meta))
+    } else {
+        try!(cargo_target_by_message(input, manifest, use_bincache, meta, opts))
+    };
+
+    trace!(".. exe_path: {:?}", exe_path);
+
+    // Before we return, cache the result.
+    {
+        let manifest_path = Path::new(manifest);
+        let package_path = manifest_path.parent().unwrap();
+        let cache_path = package_path.join("target.exe_path");
+
+        try!(util::write_atomic(&cache_path, |f| platform::write_path(f, &exe_path)));
+    }
+
+    Ok(exe_path)
+}
+
+/**
+Figures out where the output executable for the input should be by guessing.
+
+Depending on the configuration, this might not work.  On the other hand, this actually works (usually) prior to Cargo 0.18 (Rust 1.17).
+*/
+fn cargo_target_by_guess(input: &Input, use_bincache: bool, pkg_path: &Path, meta: &PackageMetadata) -> Result<PathBuf> {
+    trace!("cargo_target_by_guess(_, {:?}, {:?}, _)", use_bincache, pkg_path);
+
+    // Library file names vary too much by crate type and platform to guess at.
+    if let Some(ref crate_type) = meta.crate_type {
+        return Err((Blame::Human, format!("cannot find the `{}` library that was built: this needs Cargo 0.18 or later", crate_type)).into());
+    }
+
+    // Cargo puts the built-in profiles in directories named after what they're *for*.
+    let profile = match meta.profile.as_ref().map(|s| &**s) {
+        Some("dev") | Some("test") => "debug",
+        Some("bench") => "release",
+        Some(profile) => profile,
+        None if meta.debug => "debug",
+        None => "release",
+    };
+    let mut target_path = if use_bincache {
+        try!(get_shared_target_path(meta))
+    } else {
+        pkg_path.join("target")
+    };
+    if let Some(ref target) = meta.target {
+        target_path.push(target);
+    }
+    let bin_name = meta.bin.clone().unwrap_or_else(|| input.package_name());
+    let mut exe_path = target_path.join(profile).join(&bin_name).into_os_string();
+    exe_path.push(std::env::consts::EXE_SUFFIX);
+    Ok(exe_path.into())
+}
+
+/**
+Gets the path to the package's target file by parsing the output of `cargo build`.
+
+This only works on Cargo 0.18 (Rust 1.17) and higher.
+*/
+fn cargo_target_by_message(input: &Input, manifest: &str, use_bincache: bool, meta: &PackageMetadata, opts: &CargoOptions) -> Result<PathBuf> {
+    use std::io::{BufRead, BufReader};
+    use rustc_serialize::json;
+
+    trace!("cargo_target_by_message(_, {:?}, {:?}, _)", manifest, use_bincache);
+
+    let mut cmd = try!(cargo("build", manifest, use_bincache, meta, opts));
+    cmd.arg("--message-format=json");
+    cmd.stdout(process::Stdio::piped());
+    cmd.stderr(process::Stdio::null());
+
+    trace!(".. cmd: {:?}", *cmd);
+
+    cmd.print();
+    let mut child = try!(cmd.spawn().map_err(cargo_spawn_error));
+    match try!(child.wait()).code() {
+        Some(0) => (),
+        Some(st) => return Err(format!("could not determine target filename: cargo exited with status {}", st).into()),
+        None => return Err(format!("could not determine target filename: cargo exited abnormally").into()),
+    }
+
+    let mut line = String::with_capacity(1024);
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let null = json::Json::Null;
+    let bin_name = meta.bin.clone().unwrap_or_else(|| input.package_name());
+
+    /*
+    A library target isn't named after the package the way a binary is: Cargo swaps `-` for `_`, and the script's manifest can name it something else entirely.  So we go by what kind of target it is instead.  The package itself is built after all of its dependencies, so if one of those has the same kind, the *last* match is still ours.
+    */
+    let is_wanted = |msg: &json::Json| match meta.crate_type {
+        Some(ref crate_type) => msg.find_path(&["target", "kind"])
+            .and_then(|kind| kind.as_array())
+            .map(|kind| kind.iter().any(|k| k.as_string() == Some(crate_type)))
+            .unwrap_or(false),
+        None => msg.find_path(&["target", "name"]).unwrap_or(&null).as_string() == Some(&bin_name),
+    };
+
+    let mut found: Option<PathBuf> = None;
+    let mut line_num = 0;
+    loop {
+        line_num += 1;
+        line.clear();
+        let bytes = try!(stdout.read_line(&mut line));
+        trace!(".. line {}, {}b: {:?}", line_num, bytes, line);
+        if bytes == 0 {
+            return found.ok_or("could not determine target filename: did not find appropriate cargo message".into());
+        }
+
+        let msg = try!(json::Json::from_str(line.trim())
+            .map_err(Box::new));
+
+        // Is this the message we're looking for?
+        if msg.find("reason").unwrap_or(&null).as_string() != Some("compiler-artifact") {
+            trace!("   couldn't find `compiler-artifact`");
+            continue;
+        }
+        if !is_wanted(&msg) {
+            trace!("   not the target we built for {:?}", bin_name);
+            continue;
+        }
+
+        // Looks like it; grab the path.
+        let exe_path = msg.find_path(&["filenames"])
+            .expect("could not find `filenames` in json message")
+            .as_array()
+            .expect("`filenames` in json message was not an array")
+            [0]
+            .as_string()
+            .expect("`filenames[0]` in json message was not a string");
+
+        if meta.crate_type.is_none() {
+            return Ok(exe_path.into());
+        }
+        found = Some(exe_path.into());
+    }
+}
+
+/**
+Runs the given source through `rustfmt`, returning the formatted result.
+*/
+fn rustfmt(src: &str) -> Result<String> {
+    let mut cmd = Command::new("rustfmt");
+    cmd.stdin(process::Stdio::piped());
+    cmd.stdout(process::Stdio::piped());
+    cmd.stderr(process::Stdio::null());
+
+    let mut child = try!(cmd.spawn()
+        .map_err(MainError::from)
+        .err_tag("could not run rustfmt"));
+    {
+        let mut stdin = child.stdin.take().expect("no stdin pipe found");
+        try!(stdin.write_all(src.as_bytes()));
+    }
+
+    let output = try!(child.wait_with_output());
+    match output.status.code() {
+        Some(0) => (),
+        Some(st) => return Err(format!("rustfmt exited with status {}", st).into()),
+        None => return Err("rustfmt exited abnormally".into()),
+    }
+
+    Ok(try!(String::from_utf8(output.stdout)
+        .map_err(Box::new)))
+}
+
+/**
+Get the version of the currently active cargo, or that of a specific rustup toolchain.
+*/
+fn cargo_version(toolchain: Option<&str>) -> Result<Version> {
+    use regex::Regex;
+
+    lazy_static! {
+        static ref RE_VERSION: Regex = Regex::new(r#"^cargo[ -](\S+)"#).unwrap();
+    }
+
+    let mut cmd = Command::new("cargo");
+    if let Some(toolchain) = toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    cmd.arg("-V");
+
+    let child = try!(cmd.output().map_err(cargo_spawn_error));
+    match child.status.code() {
+        Some(0) => (),
+        Some(_) if toolchain.is_some() && String::from_utf8_lossy(&child.stderr).contains("is not installed") => {
+            let toolchain = toolchain.unwrap();
+            return Err((Blame::Human, format!("the `{}` toolchain is not installed; install it with `rustup toolchain install {}`", toolchain, toolchain)).into());
+        },
+        Some(st) => return Err(format!("could not determine cargo version: cargo exited with status {}", st).into()),
+        None => return Err(format!("could not determine cargo version: cargo exited abnormally").into()),
+    }
+
+    let stdout = String::from_utf8_lossy(&child.stdout);
+    let m = match RE_VERSION.captures(&stdout) {
+        Some(m) => m,
+        None => return Err(format!("could not determine cargo version: output did not match expected").into()),
+    };
+
+    let ver = m.get(1).unwrap();
+    Ok(try!(Version::parse(ver.as_str())
+        .map_err(Box::new)))
+}
+
+/**
+Turns a failure to run Cargo into an error a newcomer can do something about.
+
+If `cargo` can't be found at all, the raw OS error ("No such file or directory") doesn't say what's actually wrong.
+*/
+fn cargo_spawn_error(err: std::io::Error) -> MainError {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => (Blame::Human, "could not run `cargo`: it isn't on your PATH.  If Rust isn't installed, rustup (https://rustup.rs) will install it; otherwise, add Cargo's `bin` directory (usually `~/.cargo/bin`) to your PATH").into(),
+        _ => err.into(),
+    }
+}
+
+/**
+Is the active cargo (or that of a specific rustup toolchain) a nightly build?
+*/
+fn cargo_is_nightly(toolchain: Option<&str>) -> Result<bool> {
+    use semver::Identifier;
+
+    let cargo_ver = try!(cargo_version(toolchain)
+        .err_tag("could not determine whether cargo is a nightly build"));
+    Ok(cargo_ver.pre.iter().any(|id| *id == Identifier::AlphaNumeric("nightly".into())))
+}
+
+/**
+Gets the version of the compiler Cargo will use, so that switching compilers invalidates cached builds.
+
+This respects `$RUSTC`, as Cargo does.  If the compiler can't be queried, this returns `None`, and the build is left for Cargo to fail on.
+*/
+fn rustc_version(toolchain: Option<&str>) -> Option<String> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or("rustc".into());
+    let mut cmd = Command::new(rustc);
+    if let Some(toolchain) = toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    cmd.arg("-V");
+
+    match cmd.output() {
+        Ok(ref out) if out.status.success() => {
+            Some(String::from_utf8_lossy(&out.stdout).trim().into())
+        },
+        Ok(out) => {
+            info!("could not determine rustc version: rustc exited with {}", out.status);
+            None
+        },
+        Err(err) => {
+            info!("could not determine rustc version: {}", err);
+            None
+        },
+    }
+}
+
+/**
+Do we need to work around [issue #50](https://github.com/DanielKeep/cargo-script/issues/50)?
+
+Sometimes, `cargo-script` will hang when trying to read the JSON output of `cargo build`.
+*/
+fn work_around_issue_50() -> bool {
+    let suffers = cfg!(issue_50);
+    let ignored = std::env::var_os("CARGO_SCRIPT_IGNORE_ISSUE_50").is_some();
+    match (suffers, ignored) {
+        (true, true) => { trace!(".. issue 50 relevant, but ignored"); false },
+        (true, false) => { trace!(".. working around issue 50"); true },
+        (false, _) => { false },
+    }
+}
@@ -16,11 +16,13 @@ extern crate regex;
 use std::collections::HashMap;
 use std::path::Path;
 use self::regex::Regex;
+use semver::VersionReq;
 use toml;
 
 use consts;
 use error::{Blame, Result};
 use templates;
+use util;
 use Input;
 
 lazy_static! {
@@ -46,38 +48,52 @@ lazy_static! {
 Splits input into a complete Cargo manifest and unadultered Rust source.
 
 Unless we have prelude items to inject, in which case it will be *slightly* adulterated.
+
+Likewise, `bindings` are statements that need to run before the script itself, such as reading the `--input` file, and `output` is a statement for printing the result of an expression other than with `{:?}`.  The template has to have somewhere to put them.
+
+`extra_manis` are manifest fragments given on the command line.  They override the defaults, but anything in the script's own manifest overrides them.
+
+Also returns the line of the source on which the user's own code starts, if it had to be wrapped in a template.
 */
-pub fn split_input(input: &Input, deps: &[(String, String)], prelude_items: &[String]) -> Result<(String, String)> {
+pub fn split_input(input: &Input, deps: &[(String, String)], extra_manis: &[String], prelude_items: &[String], bindings: &[String], output: Option<&str>, edition: Option<&str>, default_edition: Option<&str>) -> Result<(String, String, Option<usize>)> {
     let template_buf;
     let (part_mani, source, template, sub_prelude) = match *input {
-        Input::File(_, _, content, _) => {
-            assert_eq!(prelude_items.len(), 0);
+        Input::File(_, _, content, _, program) => {
+            // The command line only allows these for expressions and loops.
+            if prelude_items.len() > 0 || bindings.len() > 0 || output.is_some() {
+                return Err("prelude items, bindings, and output statements cannot be used with a script file".into());
+            }
             let content = strip_hashbang(content);
             let (manifest, source) = find_embedded_manifest(content)
                 .unwrap_or((Manifest::Toml(""), content));
 
-            (manifest, source, try!(templates::get_template("file")), false)
+            if program {
+                (manifest, source, try!(templates::get_template("file")), false)
+            } else {
+                (manifest, source, try!(templates::get_template("expr")), true)
+            }
         },
-        Input::Expr("meaning-of-life", None) | Input::Expr("meaning_of_life", None) => {
+        Input::Expr("meaning-of-life", None, _) | Input::Expr("meaning_of_life", None, _) => {
             (Manifest::Toml(""), r#"
                 println!("42");
                 std::process::exit(42);
             "#, try!(templates::get_template("expr")), true)
         },
-        Input::Expr(content, template) => {
+        Input::Expr(content, template, _) => {
             template_buf = try!(templates::get_template(template.unwrap_or("expr")));
             let (manifest, template_src) = find_embedded_manifest(&template_buf)
                 .unwrap_or((Manifest::Toml(""), &template_buf));
             (manifest, content, template_src.into(), true)
         },
-        Input::Loop(content, count) => {
+        Input::Loop(content, count, _) => {
             let templ = if count { "loop-count" } else { "loop" };
             (Manifest::Toml(""), content, try!(templates::get_template(templ)), true)
         },
     };
 
     let mut prelude_str;
-    let mut subs = HashMap::with_capacity(2);
+    let bindings_str;
+    let mut subs = HashMap::with_capacity(4);
     subs.insert(consts::SCRIPT_BODY_SUB, &source[..]);
 
     if sub_prelude {
@@ -90,10 +106,27 @@ pub fn split_input(input: &Input, deps: &[(String, String)], prelude_items: &[St
             prelude_str.push_str("\n");
         }
         subs.insert(consts::SCRIPT_PRELUDE_SUB, &prelude_str[..]);
+
+        if bindings.len() > 0 && !templates::uses_sub(&template, consts::SCRIPT_BINDINGS_SUB) {
+            return Err((Blame::Human, format!("the template has no `#{{{}}}` substitution, so nothing can be bound for the script", consts::SCRIPT_BINDINGS_SUB)).into());
+        }
+        bindings_str = bindings.concat();
+        subs.insert(consts::SCRIPT_BINDINGS_SUB, &bindings_str[..]);
+
+        if output.is_some() && !templates::uses_sub(&template, consts::SCRIPT_OUTPUT_SUB) {
+            return Err((Blame::Human, format!("the template has no `#{{{}}}` substitution, so the output format can't be changed", consts::SCRIPT_OUTPUT_SUB)).into());
+        }
+        subs.insert(consts::SCRIPT_OUTPUT_SUB, output.unwrap_or(consts::DEFAULT_OUTPUT));
     }
 
     let source = try!(templates::expand(&template, &subs));
 
+    // Complete programs go through a template too, but it's one that leaves them alone.
+    let body_line = match *input {
+        Input::File(_, _, _, _, true) => None,
+        _ => try!(templates::sub_line(&template, &subs, consts::SCRIPT_BODY_SUB)),
+    };
+
     info!("part_mani: {:?}", part_mani);
     info!("source: {:?}", source);
 
@@ -104,8 +137,20 @@ pub fn split_input(input: &Input, deps: &[(String, String)], prelude_items: &[St
     let def_mani = try!(default_manifest(input));
     let dep_mani = try!(deps_manifest(deps));
 
-    let mani = try!(merge_manifest(def_mani, part_mani));
-    let mani = try!(merge_manifest(mani, dep_mani));
+    let mut mani = def_mani;
+    for extra_mani in extra_manis {
+        let extra_mani = try!(parse_toml(extra_mani, "`--manifest`"));
+        mani = try!(merge_manifest(mani, extra_mani));
+    }
+    let mani = try!(merge_manifest(mani, part_mani));
+    let mut mani = try!(merge_manifest(mani, dep_mani));
+
+    // An edition from a config file is only a default; the script's own manifest knows better.
+    match (edition, default_edition) {
+        (Some(edition), _) => try!(set_edition(&mut mani, edition)),
+        (None, Some(edition)) if !has_edition(&mani) => try!(set_edition(&mut mani, edition)),
+        _ => (),
+    }
 
     // Fix up relative paths.
     let mani = try!(fix_manifest_paths(mani, &input.base_path()));
@@ -114,18 +159,18 @@ pub fn split_input(input: &Input, deps: &[(String, String)], prelude_items: &[St
     let mani_str = format!("{}", toml::Value::Table(mani));
     info!("mani_str: {}", mani_str);
 
-    Ok((mani_str, source))
+    Ok((mani_str, source, body_line))
 }
 
 #[test]
 fn test_split_input() {
     macro_rules! si {
-        ($i:expr) => (split_input(&$i, &[], &[]).ok())
+        ($i:expr) => (split_input(&$i, &[], &[], &[], &[], None, None, None).ok().map(|(m, s, _)| (m, s)))
     }
 
     let dummy_path: ::std::path::PathBuf = "p".into();
     let dummy_path = &dummy_path;
-    let f = |c| Input::File("n", &dummy_path, c, 0);
+    let f = |c| Input::File("n", &dummy_path, c, 0, true);
 
     macro_rules! r {
         ($m:expr, $r:expr) => (Some(($m.into(), $r.into())));
@@ -295,6 +340,193 @@ fn main() {}
 "#
         )
     );
+
+    // The script's own manifest wins over `--manifest`.
+    let extra = vec!["[dependencies]\ntime = \"0.1.0\"\nlibc = \"0.2.5\"\n\n[profile.dev]\nopt-level = 1\n".to_owned()];
+    assert_eq!(split_input(&f(
+r#"
+// cargo-deps: time="0.1.25"
+fn main() {}
+"#
+        ), &[], &extra, &[], &[], None, None, None).ok().map(|(m, _, _)| m),
+        Some(
+r#"[[bin]]
+name = "n"
+path = "n.rs"
+
+[dependencies]
+libc = "0.2.5"
+time = "0.1.25"
+
+[package]
+authors = ["Anonymous"]
+name = "n"
+version = "0.1.0"
+
+[profile]
+[profile.dev]
+opt-level = 1
+"#.into()
+        )
+    );
+
+    let err = split_input(&f("fn main() {}"), &[], &["[profile.dev".into()], &[], &[], None, None, None).unwrap_err();
+    assert!(err.to_string().starts_with("could not parse `--manifest`: "), "{}", err);
+
+    // A default edition (from a config file) gives way to the script's own, but `--edition` doesn't.
+    let edition_of = |c, edition, default_edition| split_input(&f(c), &[], &[], &[], &[], None, edition, default_edition)
+        .map(|(m, _, _)| {
+            let mani = toml::Parser::new(&m).parse().unwrap();
+            mani["package"].lookup("edition").and_then(|e| e.as_str()).map(Into::<String>::into)
+        });
+    let with_edition = "//! ```cargo\n//! [package]\n//! edition = \"2018\"\n//! ```\nfn main() {}";
+    assert_eq!(edition_of("fn main() {}", None, Some("2021")).ok(), Some(Some("2021".into())));
+    assert_eq!(edition_of(with_edition, None, Some("2021")).ok(), Some(Some("2018".into())));
+    assert_eq!(edition_of(with_edition, Some("2018"), Some("2021")).ok(), Some(Some("2018".into())));
+    assert!(edition_of(with_edition, Some("2021"), None).is_err());
+}
+
+/**
+Returns `true` if the source contains a `fn main` at the top level, *i.e.* not inside another item, a comment, or a string literal.
+
+This is used to decide whether a script file is a complete program, or should be treated as an expression.  It isn't a real lexer; it knows just enough to skip comments and literals, and to keep track of brace nesting.
+*/
+pub fn has_main_fn(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    let len = bytes.len();
+    let is_ident = |b: u8| b == b'_' || (b as char).is_alphanumeric() || b >= 0x80;
+
+    // Skips a `"`-delimited string starting at `i`, returning the index just past its end.
+    let skip_str = |mut i: usize| -> usize {
+        i += 1;
+        while i < len {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => return i + 1,
+                _ => i += 1,
+            }
+        }
+        len
+    };
+
+    let mut i = 0;
+    let mut depth = 0;
+    let mut after_fn = false;
+
+    while i < len {
+        let b = bytes[i];
+        match b {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < len && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            },
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                // Block comments nest.
+                let mut nesting = 0;
+                while i < len {
+                    if bytes[i..].starts_with(b"/*") {
+                        nesting += 1;
+                        i += 2;
+                    } else if bytes[i..].starts_with(b"*/") {
+                        nesting -= 1;
+                        i += 2;
+                        if nesting == 0 { break; }
+                    } else {
+                        i += 1;
+                    }
+                }
+            },
+            b'"' => {
+                i = skip_str(i);
+                after_fn = false;
+            },
+            b'\'' => {
+                // Either a character literal, or a lifetime.
+                if bytes.get(i + 1) == Some(&b'\\') {
+                    i += 3;
+                    while i < len && bytes[i] != b'\'' {
+                        i += 1;
+                    }
+                    i += 1;
+                } else {
+                    match s[i + 1..].chars().next() {
+                        Some(c) if bytes.get(i + 1 + c.len_utf8()) == Some(&b'\'') => i += 2 + c.len_utf8(),
+                        _ => i += 1,
+                    }
+                }
+                after_fn = false;
+            },
+            b'{' => {
+                depth += 1;
+                i += 1;
+                after_fn = false;
+            },
+            b'}' => {
+                if depth > 0 { depth -= 1; }
+                i += 1;
+                after_fn = false;
+            },
+            _ if is_ident(b) => {
+                let start = i;
+                while i < len && is_ident(bytes[i]) {
+                    i += 1;
+                }
+                let word = &s[start..i];
+
+                // Raw and byte strings look like identifiers to begin with.
+                if (word == "r" || word == "br") && i < len && (bytes[i] == b'"' || bytes[i] == b'#') {
+                    let hashes = bytes[i..].iter().take_while(|&&b| b == b'#').count();
+                    if bytes.get(i + hashes) == Some(&b'"') {
+                        let mut close = String::from("\"");
+                        close.extend(::std::iter::repeat('#').take(hashes));
+                        i = match s[i + hashes + 1..].find(&close) {
+                            Some(end) => i + hashes + 1 + end + close.len(),
+                            None => len,
+                        };
+                        after_fn = false;
+                        continue;
+                    }
+                } else if word == "b" && i < len && bytes[i] == b'"' {
+                    i = skip_str(i);
+                    after_fn = false;
+                    continue;
+                }
+
+                if depth == 0 {
+                    if after_fn && word == "main" {
+                        return true;
+                    }
+                    after_fn = word == "fn";
+                }
+            },
+            _ if (b as char).is_whitespace() => i += 1,
+            _ => {
+                i += 1;
+                after_fn = false;
+            },
+        }
+    }
+
+    false
+}
+
+#[test]
+fn test_has_main_fn() {
+    assert!(has_main_fn("fn main() {}"));
+    assert!(has_main_fn("#!/usr/bin/env run-cargo-script\nuse std::io;\npub fn main() {}"));
+    assert!(has_main_fn("fn helper() -> char { '}' }\nfn\n    main() {}"));
+    assert!(has_main_fn("fn quote() -> char { '\\'' }\nfn main() {}"));
+    assert!(has_main_fn("fn helper<'a>(s: &'a str) -> &'a str { s }\nfn main() {}"));
+    assert!(has_main_fn("const S: &str = r#\"fn main() { \"}\" }\"#;\nfn main() {}"));
+
+    assert!(!has_main_fn("1 + 2"));
+    assert!(!has_main_fn("// fn main() {}\nprintln!(\"hi\")"));
+    assert!(!has_main_fn("/* nested /* fn main() {} */ still a comment */ 3"));
+    assert!(!has_main_fn("println!(\"fn main() {{}}\")"));
+    assert!(!has_main_fn("mod m { fn main() {} }\nm::main()"));
+    assert!(!has_main_fn("fn main_loop() {}\nmain_loop()"));
+    assert!(!has_main_fn("{ fn main() {} main() }"));
 }
 
 /**
@@ -346,10 +578,8 @@ impl<'s> Manifest<'s> {
     pub fn into_toml(self) -> Result<toml::Table> {
         use self::Manifest::*;
         match self {
-            Toml(s) => Ok(try!(toml::Parser::new(s).parse()
-                .ok_or("could not parse embedded manifest"))),
-            TomlOwned(ref s) => Ok(try!(toml::Parser::new(s).parse()
-                .ok_or("could not parse embedded manifest"))),
+            Toml(s) => parse_embedded_manifest(s),
+            TomlOwned(ref s) => parse_embedded_manifest(s),
             DepList(s) => Manifest::dep_list_to_toml(s),
         }
     }
@@ -371,11 +601,48 @@ impl<'s> Manifest<'s> {
             }
         }
 
-        Ok(try!(toml::Parser::new(&r).parse()
-            .ok_or("could not parse embedded manifest")))
+        parse_embedded_manifest(&r)
+    }
+}
+
+/**
+Parses an embedded manifest, pointing at the first problem if it isn't valid TOML.
+*/
+fn parse_embedded_manifest(s: &str) -> Result<toml::Table> {
+    parse_toml(s, "embedded manifest")
+}
+
+/**
+Parses a piece of TOML from the user, pointing at the first problem if it isn't valid.  `what` is used to say where it came from.
+*/
+fn parse_toml(s: &str, what: &str) -> Result<toml::Table> {
+    let mut parser = toml::Parser::new(s);
+    match parser.parse() {
+        Some(table) => Ok(table),
+        None => {
+            let msg = match parser.errors.first() {
+                Some(err) => {
+                    let (line, col) = parser.to_linecol(err.lo);
+                    format!("could not parse {}: {} (line {}, column {})",
+                        what, err.desc, line + 1, col + 1)
+                },
+                None => format!("could not parse {}", what),
+            };
+            Err((Blame::Human, msg).into())
+        }
     }
 }
 
+#[test]
+fn test_parse_embedded_manifest() {
+    assert!(parse_embedded_manifest("[dependencies]\ntime = \"0.1.25\"").is_ok());
+
+    let err = parse_embedded_manifest("[dependencies]\ntime = 0.1.25\"").unwrap_err();
+    let err = err.to_string();
+    assert!(err.starts_with("could not parse embedded manifest: "), "{}", err);
+    assert!(err.contains("(line 2, column "), "{}", err);
+}
+
 /**
 Locates a manifest embedded in Rust source.
 
@@ -1010,6 +1277,502 @@ fn deps_manifest(deps: &[(String, String)]) -> Result<toml::Table> {
         .ok_or("could not parse dependency manifest".into())
 }
 
+/**
+Turns the script's binary target into a library of the given crate type, such as `cdylib`.
+
+If the manifest has more than one binary target, the first is taken to be the script.
+*/
+pub fn set_lib_target(mani_str: &str, crate_type: &str) -> Result<String> {
+    let mut mani = match toml::Parser::new(mani_str).parse() {
+        Some(mani) => mani,
+        None => return Err("could not parse generated manifest".into()),
+    };
+
+    let path = match mani.remove("bin") {
+        Some(toml::Value::Array(bins)) => bins.get(0)
+            .and_then(|bin| bin.lookup("path"))
+            .and_then(|path| path.as_str())
+            .map(String::from),
+        _ => None,
+    };
+    let path = try!(path.ok_or("could not find the script's binary target in the generated manifest"));
+
+    let mut lib = match mani.remove("lib") {
+        Some(toml::Value::Table(lib)) => lib,
+        _ => toml::Table::new(),
+    };
+    lib.entry("path".into()).or_insert(toml::Value::String(path));
+    lib.insert("crate-type".into(), toml::Value::Array(vec![toml::Value::String(crate_type.into())]));
+    mani.insert("lib".into(), toml::Value::Table(lib));
+
+    Ok(format!("{}", toml::Value::Table(mani)))
+}
+
+#[test]
+fn test_set_lib_target() {
+    assert_eq!(set_lib_target("[[bin]]\nname = \"n\"\npath = \"n.rs\"\n\n[package]\nname = \"n\"\n", "cdylib").ok(),
+        Some("[lib]\ncrate-type = [\"cdylib\"]\npath = \"n.rs\"\n\n[package]\nname = \"n\"\n".into()));
+    assert_eq!(set_lib_target("[[bin]]\nname = \"n\"\npath = \"n.rs\"\n\n[lib]\nname = \"l\"\n", "staticlib").ok(),
+        Some("[lib]\ncrate-type = [\"staticlib\"]\nname = \"l\"\npath = \"n.rs\"\n".into()));
+    assert!(set_lib_target("[package]\nname = \"n\"\n", "cdylib").is_err());
+}
+
+/**
+Returns the names of the binary targets in the given manifest, in the order they're declared.
+*/
+pub fn bin_names(mani_str: &str) -> Result<Vec<String>> {
+    let mani = match toml::Parser::new(mani_str).parse() {
+        Some(mani) => mani,
+        None => return Err("could not parse generated manifest".into()),
+    };
+
+    let bins = match mani.get("bin") {
+        Some(&toml::Value::Array(ref bins)) => bins,
+        _ => return Ok(vec![]),
+    };
+    Ok(bins.iter()
+        .filter_map(|bin| bin.lookup("name").and_then(|n| n.as_str()))
+        .map(Into::into)
+        .collect())
+}
+
+#[test]
+fn test_bin_names() {
+    assert_eq!(bin_names("[[bin]]\nname = \"n\"\npath = \"n.rs\"\n").ok(), Some(vec!["n".into()]));
+    assert_eq!(bin_names("[[bin]]\nname = \"a\"\n\n[[bin]]\nname = \"b\"\n").ok(), Some(vec!["a".into(), "b".into()]));
+    assert_eq!(bin_names("[package]\nname = \"n\"\n").ok(), Some(vec![]));
+}
+
+/**
+Does the given manifest declare any features of its own?
+*/
+pub fn declares_features(mani_str: &str) -> bool {
+    toml::Parser::new(mani_str).parse()
+        .and_then(|mani| mani.get("features").and_then(|fs| fs.as_table()).map(|fs| fs.len() > 0))
+        .unwrap_or(false)
+}
+
+#[test]
+fn test_declares_features() {
+    assert_eq!(declares_features("[package]\nname = \"n\"\n"), false);
+    assert_eq!(declares_features("[features]\n"), false);
+    assert_eq!(declares_features("[features]\nfoo = []\n"), true);
+    assert_eq!(declares_features("[dependencies.a]\nfeatures = [\"foo\"]\n"), false);
+}
+
+/**
+Which features of a dependency to enable, as given by `--dep-features`.
+*/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DepFeatures {
+    pub default_features: bool,
+    pub features: Vec<String>,
+}
+
+/**
+Parses a `--dep-features` spec of the form `name=feature,feature`.
+
+A leading `-` on the feature list turns off the dependency's default features; `name=-` does *only* that.
+*/
+pub fn parse_dep_features(spec: &str) -> Result<(String, DepFeatures)> {
+    let i = try!(spec.find('=')
+        .ok_or((Blame::Human, format!("dependency features `{}` should be of the form `name=feature,feature`", spec))));
+    let (name, features) = (&spec[..i], &spec[i+1..]);
+
+    if name == "" {
+        return Err((Blame::Human, format!("dependency features `{}` are missing a package name", spec)).into());
+    }
+
+    let (default_features, features) = match features.starts_with("-") {
+        true => (false, &features[1..]),
+        false => (true, features),
+    };
+
+    let features: Vec<String> = features.split(',')
+        .map(str::trim)
+        .filter(|f| *f != "")
+        .map(Into::into)
+        .collect();
+
+    if default_features && features.len() == 0 {
+        return Err((Blame::Human, format!("no features given for dependency '{}'", name)).into());
+    }
+
+    Ok((name.into(), DepFeatures {
+        default_features: default_features,
+        features: features,
+    }))
+}
+
+#[test]
+fn test_parse_dep_features() {
+    macro_rules! pdf {
+        ($s:expr) => (parse_dep_features($s).ok().map(|(n, df)| (n, df.default_features, df.features)));
+    }
+
+    macro_rules! r {
+        ($n:expr, $d:expr, [$($fs:expr),*]) => (Some(($n.into(), $d, vec![$(String::from($fs)),*])));
+    }
+
+    assert_eq!(pdf!("serde=derive"), r!("serde", true, ["derive"]));
+    assert_eq!(pdf!("serde=derive,rc"), r!("serde", true, ["derive", "rc"]));
+    assert_eq!(pdf!("serde=derive, rc,"), r!("serde", true, ["derive", "rc"]));
+    assert_eq!(pdf!("serde=-derive,rc"), r!("serde", false, ["derive", "rc"]));
+    assert_eq!(pdf!("serde=-"), r!("serde", false, []));
+    assert_eq!(pdf!("serde="), None);
+    assert_eq!(pdf!("serde"), None);
+    assert_eq!(pdf!("=derive"), None);
+}
+
+/**
+Works out the script's dependencies from `--dep`/`--dep-extern` specs (`name` or `name=spec`), `--dep-features` specs, and `--dep-no-default` names.
+
+The result is sorted, so that it hashes consistently, and each spec has been run through `expand_dep_spec`.
+
+A dependency can be given more than once.  Features for it accumulate, as does turning off its default features.  Giving the same spec again does nothing, and a bare `name` (which means any version) gives way to a more specific spec, whichever order they come in.  Two *different* specs for the same dependency are an error, since there's no telling which one was meant.
+*/
+pub fn merge_deps(deps: &[&str], dep_features: &[String], dep_no_default: &[String]) -> Result<Vec<(String, String)>> {
+    use std::collections::hash_map::Entry::{Occupied, Vacant};
+
+    let mut features: HashMap<String, DepFeatures> = HashMap::new();
+    for spec in dep_features {
+        let (name, df) = try!(parse_dep_features(spec));
+        match features.entry(name) {
+            Vacant(ve) => {
+                ve.insert(df);
+            },
+            Occupied(mut oe) => {
+                let existing = oe.get_mut();
+                existing.default_features &= df.default_features;
+                existing.features.extend(df.features);
+            }
+        }
+    }
+    for name in dep_no_default {
+        features.entry(name.clone())
+            .or_insert(DepFeatures {
+                default_features: true,
+                features: vec![],
+            })
+            .default_features = false;
+    }
+    for df in features.values_mut() {
+        df.features.sort();
+        df.features.dedup();
+    }
+
+    // Each dependency maps to the spec as given, and what it expanded to.
+    let mut merged: HashMap<String, (String, String)> = HashMap::new();
+    for dep in deps {
+        let (name, spec) = match dep.find('=') {
+            Some(i) => (&dep[..i], &dep[i+1..]),
+            None => (&dep[..], "*"),
+        };
+
+        if name == "" {
+            return Err((Blame::Human, "cannot have empty dependency package name").into());
+        }
+
+        if spec == "" {
+            return Err((Blame::Human, format!("cannot have empty dependency version for '{}'", name)).into());
+        }
+
+        let expanded = try!(expand_dep_spec(name, spec, features.get(name)));
+
+        match merged.entry(name.into()) {
+            Vacant(ve) => {
+                ve.insert((spec.into(), expanded));
+            },
+            Occupied(mut oe) => {
+                let existing = oe.get_mut();
+                if existing.1 == expanded || spec == "*" {
+                    // Nothing new.
+                } else if existing.0 == "*" {
+                    *existing = (spec.into(), expanded);
+                } else {
+                    return Err((Blame::Human,
+                        format!("conflicting versions for dependency '{}': '{}', '{}'",
+                            name, existing.0, spec)).into());
+                }
+            }
+        }
+    }
+
+    for name in features.keys() {
+        if !merged.contains_key(name) {
+            return Err((Blame::Human,
+                format!("features given for '{}', which is not a dependency", name)).into());
+        }
+    }
+
+    let mut deps: Vec<(String, String)> = merged.into_iter()
+        .map(|(name, (_, expanded))| (name, expanded))
+        .collect();
+    deps.sort();
+    Ok(deps)
+}
+
+#[test]
+fn test_merge_deps() {
+    fn md(deps: &[&str], features: &[&str], no_default: &[&str]) -> Option<Vec<(String, String)>> {
+        let features: Vec<String> = features.iter().map(|&s| s.into()).collect();
+        let no_default: Vec<String> = no_default.iter().map(|&s| s.into()).collect();
+        merge_deps(deps, &features, &no_default).ok()
+    }
+
+    macro_rules! deps {
+        ($($name:expr => $spec:expr),*) => (Some(vec![$(($name.into(), $spec.into())),*]));
+    }
+
+    // Sorted, with missing versions filled in.
+    assert_eq!(md(&["b=0.1", "a"], &[], &[]), deps!["a" => "*", "b" => "0.1"]);
+
+    // Merge.
+    assert_eq!(md(&["a=1.0", "a=1.0"], &[], &[]), deps!["a" => "1.0"]);
+
+    // Override.
+    assert_eq!(md(&["a", "a=1.0"], &[], &[]), deps!["a" => "1.0"]);
+    assert_eq!(md(&["a=1.0", "a"], &[], &[]), deps!["a" => "1.0"]);
+
+    // Conflict.
+    assert_eq!(md(&["a=1.0", "a=0.9"], &[], &[]), None);
+    assert_eq!(md(&["a=1.0", "a=1.0@my-registry"], &[], &[]), None);
+
+    // Features accumulate, whichever flag they come from.
+    assert_eq!(md(&["a=1.0", "a"], &["a=derive", "a=rc,derive"], &[]),
+        deps!["a" => r#"{ version = "1.0", features = ["derive", "rc"] }"#]);
+    assert_eq!(md(&["a=1.0"], &["a=derive"], &["a"]),
+        deps!["a" => r#"{ version = "1.0", default-features = false, features = ["derive"] }"#]);
+    assert_eq!(md(&["a=1.0"], &["b=derive"], &[]), None);
+
+    assert_eq!(md(&["=1.0"], &[], &[]), None);
+    assert_eq!(md(&["a="], &[], &[]), None);
+}
+
+/**
+Expands a dependency spec from the command line (the bit after the `=` in `--dep name=spec`) into the value that should be written into the `[dependencies]` table.
+
+Plain versions are passed through untouched, once they've been checked to be valid version requirements; anything Cargo accepts, such as `>=0.7, <0.9`, is fine.  `path:DIR` becomes a path dependency; `DIR` is relative to the current directory, *not* the script, since that's where the user typed it, and may start with `~`.  `git:URL#REF` becomes a git dependency, where the `#REF` is optional; see `git_ref_kind` for how it's interpreted.
+
+A version can be followed by `@REGISTRY` to fetch the dependency from an alternative registry, which has to be set up in Cargo's own configuration.  Path and git dependencies don't come from a registry, so any `@` in them is left alone as part of the path or URL.
+
+If `features` is given, the result will *always* be an inline table.
+*/
+pub fn expand_dep_spec(name: &str, spec: &str, features: Option<&DepFeatures>) -> Result<String> {
+    let mut pairs = vec![];
+
+    if spec.starts_with("path:") {
+        let path = &spec["path:".len()..];
+        if path == "" {
+            return Err((Blame::Human, format!("dependency '{}' has an empty path", name)).into());
+        }
+
+        let path = try!(::std::env::current_dir()).join(util::expand_tilde(path));
+        if !path.is_dir() {
+            return Err((Blame::Human, format!("path for dependency '{}' does not exist or is not a directory: {}", name, path.display())).into());
+        }
+
+        let path = try!(path.to_str()
+            .ok_or((Blame::Human, format!("path for dependency '{}' is not valid UTF-8", name))));
+        pairs.push(("path", toml::Value::String(path.into())));
+    } else if spec.starts_with("git:") {
+        let spec = &spec["git:".len()..];
+        let (url, git_ref) = match spec.find('#') {
+            Some(i) => (&spec[..i], Some(&spec[i+1..])),
+            None => (spec, None),
+        };
+
+        if url == "" {
+            return Err((Blame::Human, format!("dependency '{}' has an empty git URL", name)).into());
+        }
+        pairs.push(("git", toml::Value::String(url.into())));
+
+        if let Some(git_ref) = git_ref {
+            let (kind, git_ref) = git_ref_kind(git_ref);
+            if git_ref == "" {
+                return Err((Blame::Human, format!("dependency '{}' has an empty git {} after `#`", name, kind)).into());
+            }
+            pairs.push((kind, toml::Value::String(git_ref.into())));
+        }
+    } else {
+        let (spec, registry) = split_registry(spec);
+        if registry == Some("") {
+            return Err((Blame::Human, format!("dependency '{}' has an empty registry name after `@`", name)).into());
+        }
+
+        // Better to find out now than from Cargo, which will blame the generated manifest.
+        if let Err(err) = VersionReq::parse(spec) {
+            return Err((Blame::Human, format!("dependency '{}' has an invalid version requirement `{}`: {}", name, spec, err)).into());
+        }
+
+        if features.is_none() && registry.is_none() {
+            return Ok(spec.into());
+        }
+        pairs.push(("version", toml::Value::String(spec.into())));
+        if let Some(registry) = registry {
+            pairs.push(("registry", toml::Value::String(registry.into())));
+        }
+    }
+
+    if let Some(features) = features {
+        if !features.default_features {
+            pairs.push(("default-features", toml::Value::Boolean(false)));
+        }
+        if features.features.len() > 0 {
+            let fs = features.features.iter().cloned().map(toml::Value::String).collect();
+            pairs.push(("features", toml::Value::Array(fs)));
+        }
+    }
+
+    Ok(inline_table(&pairs))
+}
+
+/**
+Splits a trailing `@REGISTRY` off a version requirement.
+
+Only something that looks like a registry name counts.  This must not be used on path or git specs, where an `@` is just part of the path or URL.
+*/
+fn split_registry(spec: &str) -> (&str, Option<&str>) {
+    match spec.rfind('@') {
+        Some(i) if spec[i+1..].chars().all(|c| c == '-' || c == '_' || c.is_alphanumeric()) => {
+            (&spec[..i], Some(&spec[i+1..]))
+        },
+        _ => (spec, None),
+    }
+}
+
+/**
+Works out whether a git ref is a branch, tag, or revision.
+
+An explicit `branch=`, `tag=`, or `rev=` prefix always wins.  Otherwise, anything that looks like an abbreviated or full commit hash is a `rev`, anything that looks like a version number (with or without a leading `v`) is a `tag`, and everything else is a `branch`.
+*/
+fn git_ref_kind(git_ref: &str) -> (&'static str, &str) {
+    for &kind in &["branch", "tag", "rev"] {
+        if git_ref.starts_with(kind) && git_ref[kind.len()..].starts_with("=") {
+            return (kind, &git_ref[kind.len()+1..]);
+        }
+    }
+
+    let is_hash = 7 <= git_ref.len() && git_ref.len() <= 40
+        && git_ref.chars().all(|c| c.is_digit(16));
+    if is_hash {
+        return ("rev", git_ref);
+    }
+
+    let is_version = {
+        let v = if git_ref.starts_with("v") { &git_ref[1..] } else { git_ref };
+        v.chars().next().map(|c| c.is_digit(10)).unwrap_or(false)
+            && v.chars().all(|c| c.is_digit(10) || c == '.')
+    };
+    if is_version {
+        return ("tag", git_ref);
+    }
+
+    ("branch", git_ref)
+}
+
+/**
+Formats key/value pairs as an inline TOML table.
+*/
+fn inline_table(pairs: &[(&str, toml::Value)]) -> String {
+    let pairs: Vec<_> = pairs.iter()
+        .map(|&(k, ref v)| format!("{} = {}", k, v))
+        .collect();
+    format!("{{ {} }}", pairs.join(", "))
+}
+
+#[test]
+fn test_expand_dep_spec() {
+    let cwd = ::std::env::current_dir().unwrap();
+    let cwd_src = toml::Value::String(cwd.join("src").to_str().unwrap().into());
+
+    assert_eq!(expand_dep_spec("a", "0.1.0", None).ok(), Some("0.1.0".into()));
+    assert_eq!(expand_dep_spec("a", "*", None).ok(), Some("*".into()));
+    assert_eq!(expand_dep_spec("a", "^1.2", None).ok(), Some("^1.2".into()));
+    assert_eq!(expand_dep_spec("a", "~0.3.1", None).ok(), Some("~0.3.1".into()));
+    assert_eq!(expand_dep_spec("a", "=0.1.0", None).ok(), Some("=0.1.0".into()));
+    assert_eq!(expand_dep_spec("a", ">=0.7, <0.9", None).ok(), Some(">=0.7, <0.9".into()));
+    assert!(expand_dep_spec("a", "0.1.x.y", None).is_err());
+    assert!(expand_dep_spec("a", ">=0.7 <0.9 \"", None).is_err());
+    assert!(expand_dep_spec("a", "latest", None).is_err());
+    assert_eq!(expand_dep_spec("a", "1.0@my-registry", None).ok(),
+        Some(r#"{ version = "1.0", registry = "my-registry" }"#.into()));
+    assert_eq!(expand_dep_spec("a", ">=0.7, <0.9@my_registry", None).ok(),
+        Some(r#"{ version = ">=0.7, <0.9", registry = "my_registry" }"#.into()));
+    assert!(expand_dep_spec("a", "1.0@", None).is_err());
+    assert!(expand_dep_spec("a", "path:src@my-registry", None).is_err());
+    assert_eq!(expand_dep_spec("a", "path:src", None).ok(), Some(format!("{{ path = {} }}", cwd_src)));
+    assert!(expand_dep_spec("a", "path:", None).is_err());
+    assert!(expand_dep_spec("a", "path:does/not/exist", None).is_err());
+    assert!(expand_dep_spec("a", "path:src/main.rs", None).is_err());
+
+    // An `@` in a path is part of the path, not a registry.
+    {
+        extern crate tempdir;
+        let tmp = tempdir::TempDir::new("cargo-script-test").unwrap();
+        let dir = tmp.path().join("crate@v2");
+        ::std::fs::create_dir(&dir).unwrap();
+        let dir_str = dir.to_str().unwrap();
+        assert_eq!(expand_dep_spec("a", &format!("path:{}", dir_str), None).ok(),
+            Some(format!("{{ path = {} }}", toml::Value::String(dir_str.into()))));
+    }
+
+    const URL: &'static str = "https://github.com/user/repo";
+    macro_rules! git {
+        ($spec:expr) => (expand_dep_spec("a", &format!("git:{}{}", URL, $spec), None).ok());
+    }
+
+    assert_eq!(git!(""), Some(format!(r#"{{ git = "{}" }}"#, URL)));
+    assert_eq!(git!("#master"), Some(format!(r#"{{ git = "{}", branch = "master" }}"#, URL)));
+    assert_eq!(git!("#feature/thing"), Some(format!(r#"{{ git = "{}", branch = "feature/thing" }}"#, URL)));
+    assert_eq!(git!("#v0.1.2"), Some(format!(r#"{{ git = "{}", tag = "v0.1.2" }}"#, URL)));
+    assert_eq!(git!("#1.0"), Some(format!(r#"{{ git = "{}", tag = "1.0" }}"#, URL)));
+    assert_eq!(git!("#9fceb02"), Some(format!(r#"{{ git = "{}", rev = "9fceb02" }}"#, URL)));
+    assert_eq!(git!("#branch=1.0"), Some(format!(r#"{{ git = "{}", branch = "1.0" }}"#, URL)));
+    assert_eq!(git!("#tag=release"), Some(format!(r#"{{ git = "{}", tag = "release" }}"#, URL)));
+    assert_eq!(git!("#rev=HEAD~1"), Some(format!(r#"{{ git = "{}", rev = "HEAD~1" }}"#, URL)));
+    assert_eq!(expand_dep_spec("a", "git:git@github.com:user/repo", None).ok(),
+        Some(r#"{ git = "git@github.com:user/repo" }"#.into()));
+    assert_eq!(expand_dep_spec("a", "git:https://github.com/user/repo@my-fork", None).ok(),
+        Some(r#"{ git = "https://github.com/user/repo@my-fork" }"#.into()));
+    assert_eq!(git!("#feature@2"), Some(format!(r#"{{ git = "{}", branch = "feature@2" }}"#, URL)));
+    assert_eq!(git!("#"), None);
+    assert_eq!(git!("#rev="), None);
+    assert_eq!(expand_dep_spec("a", "git:", None).ok(), None);
+    assert_eq!(expand_dep_spec("a", "git:#master", None).ok(), None);
+
+    // Make sure that features make it into the manifest intact.
+    macro_rules! feat {
+        ($spec:expr, $features:expr) => {
+            {
+                let (_, df) = parse_dep_features(&format!("a={}", $features)).unwrap();
+                let value = expand_dep_spec("a", $spec, Some(&df)).unwrap();
+                let mani = toml::Parser::new(&format!("a = {}", value)).parse().unwrap();
+                (value, mani["a"].clone())
+            }
+        }
+    }
+
+    let (value, parsed) = feat!("1.0", "derive,rc");
+    assert_eq!(value, r#"{ version = "1.0", features = ["derive", "rc"] }"#);
+    assert_eq!(parsed.lookup("version").and_then(|v| v.as_str()), Some("1.0"));
+    assert_eq!(parsed.lookup("default-features"), None);
+    assert_eq!(parsed.lookup("features").and_then(|v| v.as_slice()).map(|fs| fs.len()), Some(2));
+
+    let (value, parsed) = feat!("1.0", "-derive");
+    assert_eq!(value, r#"{ version = "1.0", default-features = false, features = ["derive"] }"#);
+    assert_eq!(parsed.lookup("default-features").and_then(|v| v.as_bool()), Some(false));
+
+    let (value, _) = feat!("1.0", "-");
+    assert_eq!(value, r#"{ version = "1.0", default-features = false }"#);
+
+    let (value, _) = feat!("1.0@my-registry", "derive");
+    assert_eq!(value, r#"{ version = "1.0", registry = "my-registry", features = ["derive"] }"#);
+
+    let (value, _) = feat!(&format!("git:{}#v1.0", URL), "-");
+    assert_eq!(value, format!(r#"{{ git = "{}", tag = "v1.0", default-features = false }}"#, URL));
+}
+
 /**
 Given two Cargo manifests, merges the second *into* the first.
 
@@ -1051,6 +1814,143 @@ fn merge_manifest(mut into_t: toml::Table, from_t: toml::Table) -> Result<toml::
     }
 }
 
+/**
+Sets the edition of the package in the given manifest.
+
+If the manifest *already* specifies a different edition, that's an error: we can't tell which one the user actually wanted.
+*/
+fn set_edition(mani: &mut toml::Table, edition: &str) -> Result<()> {
+    let package = match mani.get_mut("package") {
+        Some(&mut toml::Value::Table(ref mut package)) => package,
+        _ => return Err("cannot set edition: manifest has no package table".into())
+    };
+
+    match package.get("edition") {
+        None => (),
+        Some(&toml::Value::String(ref e)) if e == edition => return Ok(()),
+        Some(e) => return Err((Blame::Human, format!("cannot set edition to {}: the script's manifest already specifies edition {}", edition, e)).into())
+    }
+
+    package.insert("edition".into(), toml::Value::String(edition.into()));
+    Ok(())
+}
+
+/**
+Does the given manifest say which edition its package uses?
+*/
+fn has_edition(mani: &toml::Table) -> bool {
+    mani.get("package")
+        .and_then(|p| p.as_table())
+        .map(|p| p.contains_key("edition"))
+        .unwrap_or(false)
+}
+
+#[test]
+fn test_set_edition() {
+    macro_rules! se {
+        ($m:expr, $e:expr) => {
+            {
+                let mut mani = toml::Parser::new($m).parse().unwrap();
+                set_edition(&mut mani, $e).ok().map(|()| format!("{}", toml::Value::Table(mani)))
+            }
+        }
+    }
+
+    assert_eq!(se!("[package]\nname = \"n\"\n", "2018"),
+        Some("[package]\nedition = \"2018\"\nname = \"n\"\n".into()));
+    assert_eq!(se!("[package]\nedition = \"2018\"\nname = \"n\"\n", "2018"),
+        Some("[package]\nedition = \"2018\"\nname = \"n\"\n".into()));
+    assert_eq!(se!("[package]\nedition = \"2015\"\nname = \"n\"\n", "2018"), None);
+    assert_eq!(se!("[dependencies]\n", "2018"), None);
+}
+
+/**
+Points every binary target in the given manifest at `path`.
+
+Generated packages keep the script next to the manifest; this is for when it needs to live somewhere more conventional.
+*/
+pub fn set_bin_path(mani_str: &str, path: &str) -> Result<String> {
+    let mani = match toml::Parser::new(mani_str).parse() {
+        Some(mani) => mani,
+        None => return Err("could not parse generated manifest".into()),
+    };
+
+    let mut mani = toml::Value::Table(mani);
+    try!(iterate_toml_mut_path(&mut mani, &["bin", "*", "path"], &mut |v| {
+        *v = toml::Value::String(path.into());
+        Ok(())
+    }));
+    Ok(format!("{}", mani))
+}
+
+#[test]
+fn test_set_bin_path() {
+    assert_eq!(set_bin_path("[[bin]]\nname = \"n\"\npath = \"n.rs\"\n", "src/main.rs").ok(),
+        Some("[[bin]]\nname = \"n\"\npath = \"src/main.rs\"\n".into()));
+    assert_eq!(set_bin_path("[package]\nname = \"n\"\n", "src/main.rs").ok(),
+        Some("[package]\nname = \"n\"\n".into()));
+}
+
+/**
+Returns the name and directory of every path dependency in the given manifest, including target-specific ones.
+*/
+pub fn path_deps(mani_str: &str) -> Result<Vec<(String, String)>> {
+    let mani = match toml::Parser::new(mani_str).parse() {
+        Some(mani) => mani,
+        None => return Err("could not parse generated manifest".into()),
+    };
+
+    fn collect(deps: Option<&toml::Value>, found: &mut Vec<(String, String)>) {
+        if let Some(&toml::Value::Table(ref deps)) = deps {
+            for (name, dep) in deps {
+                if let toml::Value::Table(ref dep) = *dep {
+                    if let Some(&toml::Value::String(ref path)) = dep.get("path") {
+                        found.push((name.clone(), path.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    const DEP_TABLES: &'static [&'static str] = &["dependencies", "build-dependencies", "dev-dependencies"];
+
+    let mut found = vec![];
+    for table in DEP_TABLES {
+        collect(mani.get(*table), &mut found);
+    }
+    if let Some(&toml::Value::Table(ref targets)) = mani.get("target") {
+        for (_, target) in targets {
+            if let toml::Value::Table(ref target) = *target {
+                for table in DEP_TABLES {
+                    collect(target.get(*table), &mut found);
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+#[test]
+fn test_path_deps() {
+    let mani = r#"
+[dependencies]
+a = "0.1"
+b = { path = "/src/b" }
+
+[dev-dependencies]
+c = { version = "0.2", path = "/src/c" }
+
+[target.'cfg(unix)'.dependencies]
+d = { path = "/src/d" }
+"#;
+    assert_eq!(path_deps(mani).ok(), Some(vec![
+        ("b".into(), "/src/b".into()),
+        ("c".into(), "/src/c".into()),
+        ("d".into(), "/src/d".into()),
+    ]));
+    assert_eq!(path_deps("[dependencies]\na = \"0.1\"\n").ok(), Some(vec![]));
+}
+
 /**
 Given a Cargo manifest, attempts to rewrite relative file paths to absolute ones, allowing the manifest to be relocated.
 */
@@ -1109,6 +2009,11 @@ where F: FnMut(&mut toml::Value) -> Result<()> {
                     try!(iterate_toml_mut_path(v, tail, on_each));
                 }
             },
+            toml::Value::Array(ref mut arr) => {
+                for v in arr {
+                    try!(iterate_toml_mut_path(v, tail, on_each));
+                }
+            },
             _ => {},
         }
     } else {
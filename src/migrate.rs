@@ -0,0 +1,75 @@
+/*
+Copyright ⓒ 2017 cargo-script contributors.
+
+Licensed under the MIT license (see LICENSE or <http://opensource.org
+/licenses/MIT>) or the Apache License, Version 2.0 (see LICENSE of
+<http://www.apache.org/licenses/LICENSE-2.0>), at your option. All
+files in the project carrying such notice may not be copied, modified,
+or distributed except according to those terms.
+*/
+/*!
+This module contains the `migrate` subcommand, which moves data left behind by older versions to where this version expects it.
+*/
+use clap;
+use error::Result;
+use platform::{self, MigrationKind};
+
+#[derive(Debug)]
+pub struct Args {
+    kind: MigrationKind,
+}
+
+/**
+Everything the subcommand can be called; just its name, since it has no aliases.
+*/
+pub const NAMES: &'static [&'static str] = &["migrate"];
+
+impl Args {
+    pub fn subcommand() -> clap::App<'static, 'static> {
+        use clap::{Arg, SubCommand};
+
+        SubCommand::with_name(NAMES[0])
+            .about("Migrate data from older versions.")
+
+            .arg(Arg::with_name("dry_run")
+                .help("Report what would be migrated without actually moving anything.")
+                .long("dry-run")
+            )
+    }
+
+    pub fn parse(m: &clap::ArgMatches) -> Self {
+        Args {
+            kind: match m.is_present("dry_run") {
+                true => MigrationKind::DryRun,
+                false => MigrationKind::ForReal,
+            },
+        }
+    }
+}
+
+pub fn try_main(args: Args) -> Result<i32> {
+    try!(migrate(args.kind));
+    Ok(0)
+}
+
+/**
+Performs (or, for a dry run, describes) any migrations, printing what was done.
+
+This is shared with the older `script --migrate-data` option.
+*/
+pub fn migrate(kind: MigrationKind) -> Result<()> {
+    if kind.for_real() {
+        println!("Migrating data...");
+    } else {
+        println!("Checking for data to migrate (dry run)...");
+    }
+
+    let (log, res) = platform::migrate_old_data(kind);
+    if log.len() == 0 && res.is_ok() {
+        println!("Nothing needs migrating.");
+    }
+    for entry in log {
+        println!("- {}", entry);
+    }
+    res
+}
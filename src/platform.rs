@@ -11,12 +11,184 @@ or distributed except according to those terms.
 This module is for platform-specific stuff.
 */
 
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use error::{Blame, MainError};
+
 pub use self::inner::{
-    current_time, file_last_modified, get_cache_dir, get_config_dir,
-    migrate_old_data, write_path, read_path,
-    force_cargo_color,
+    exit_status_code, migrate_old_data, shell_command, status_with_timeout, write_path, read_path,
 };
 
+/**
+Gets the current system time, in milliseconds since the UNIX epoch.
+*/
+pub fn current_time() -> u64 {
+    system_time_ms(SystemTime::now())
+}
+
+/**
+Gets the last-modified time of a file, in milliseconds since the UNIX epoch.
+
+This goes through the same clock as `current_time`, so the two can be compared directly.
+*/
+pub fn file_last_modified(file: &fs::File) -> u64 {
+    file.metadata()
+        .and_then(|md| md.modified())
+        .map(system_time_ms)
+        .unwrap_or(0)
+}
+
+/**
+Converts a `SystemTime` into milliseconds since the UNIX epoch.
+
+Times prior to 1970 are going to be *somewhat rare*, so those just saturate to zero.
+*/
+fn system_time_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() * 1000 + (d.subsec_nanos() / 1_000_000) as u64)
+        .unwrap_or(0)
+}
+
+/**
+Kills something, such as a command run with `status_with_timeout`, if it's still running once its time is up.
+
+This is just a thread that sleeps for the timeout, since `Receiver::recv_timeout` needs Rust 1.12.  It can't be woken up early, so it's never joined; once `finish` has been called, it does nothing when it wakes.
+*/
+struct Watchdog {
+    state: Arc<Mutex<WatchdogState>>,
+}
+
+#[derive(Default)]
+struct WatchdogState {
+    /// The thing being watched has finished, so there's nothing to kill.
+    done: bool,
+    /// The time ran out, and it was killed.
+    fired: bool,
+}
+
+impl Watchdog {
+    fn start<F>(timeout: Duration, kill: F) -> Watchdog
+    where F: FnOnce() + Send + 'static {
+        let state = Arc::new(Mutex::new(WatchdogState::default()));
+        let thread_state = state.clone();
+        thread::spawn(move || {
+            thread::sleep(timeout);
+            // Killing under the lock means `finish` can't miss it, or let it happen afterwards.
+            let mut state = match thread_state.lock() {
+                Ok(state) => state,
+                Err(_) => return,
+            };
+            if !state.done {
+                kill();
+                state.fired = true;
+            }
+        });
+        Watchdog { state: state }
+    }
+
+    /**
+    Stops the watchdog, returning whether it had already killed its target.
+    */
+    fn finish(self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.done = true;
+        state.fired
+    }
+}
+
+#[test]
+fn test_file_last_modified() {
+    extern crate tempdir;
+    use std::io::Write;
+
+    let before = current_time();
+    let dir = tempdir::TempDir::new("cargo-script-test").unwrap();
+    let path = dir.path().join("file");
+    fs::File::create(&path).unwrap().write_all(b"hi").unwrap();
+    let mtime = file_last_modified(&fs::File::open(&path).unwrap());
+    let after = current_time();
+
+    // Filesystem timestamps can be coarser than the clock, so allow for truncation on the low end.
+    assert!(before / 1000 * 1000 <= mtime, "{} > {}", before, mtime);
+    assert!(mtime <= after, "{} > {}", mtime, after);
+}
+
+/**
+Get a directory suitable for storing user- and machine-specific data which may or may not be persisted across sessions.
+
+If `CARGO_SCRIPT_CACHE_DIR` is set (which is what `--cache-dir` does), that is used.  Otherwise, the location is platform-specific.
+
+Packages in the cache can be nested quite deeply, so on Windows this is given in extended-length form to get around `MAX_PATH`.
+*/
+pub fn get_cache_dir() -> Result<PathBuf, MainError> {
+    let dir = match try!(get_dir_override("CARGO_SCRIPT_CACHE_DIR")) {
+        Some(dir) => dir,
+        None => try!(inner::get_cache_dir()),
+    };
+    Ok(inner::extended_length_path(dir))
+}
+
+/**
+Get a directory suitable for storing user-specific configuration data.
+
+If `CARGO_SCRIPT_CONFIG_DIR` is set (which is what `--config-dir` does), that is used.  Otherwise, the location is platform-specific.
+*/
+pub fn get_config_dir() -> Result<PathBuf, MainError> {
+    match try!(get_dir_override("CARGO_SCRIPT_CONFIG_DIR")) {
+        Some(dir) => Ok(dir),
+        None => inner::get_config_dir(),
+    }
+}
+
+/**
+Works out what, if anything, to pass to Cargo's `--color` option.
+
+`setting` is what `--color` or `CARGO_SCRIPT_COLOR` was set to.  If that's `always` or `never`, it is used.  Otherwise, the decision is platform-specific.
+*/
+pub fn cargo_color(setting: Option<&str>) -> Result<Option<&'static str>, MainError> {
+    let guess = || if inner::force_cargo_color() { Some("always") } else { None };
+    match setting {
+        Some("always") => Ok(Some("always")),
+        Some("never") => Ok(Some("never")),
+        Some("auto") | Some("") => Ok(guess()),
+        Some(v) => Err((Blame::Human,
+            format!("invalid value `{}` for $CARGO_SCRIPT_COLOR: expected `auto`, `always`, or `never`", v)).into()),
+        None => Ok(guess()),
+    }
+}
+
+/**
+Checks the given environment variable for a directory override.
+
+Relative paths are resolved against the current directory, since they would otherwise change meaning depending on where we're invoked from.  If the directory doesn't exist, we try to create it *now* so that the user gets a sensible error, rather than something obscure later on.
+*/
+fn get_dir_override(var: &str) -> Result<Option<PathBuf>, MainError> {
+    let dir = match env::var_os(var) {
+        Some(ref dir) if dir.len() > 0 => PathBuf::from(dir),
+        _ => return Ok(None),
+    };
+
+    let dir = if dir.is_relative() {
+        try!(env::current_dir()).join(dir)
+    } else {
+        dir
+    };
+
+    if !dir.is_dir() {
+        if let Err(err) = fs::create_dir_all(&dir) {
+            return Err((Blame::Human,
+                format!("could not create directory `{}` (from ${}): {}",
+                    dir.display(), var, err)).into());
+        }
+    }
+
+    Ok(Some(dir))
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum MigrationKind {
     DryRun,
@@ -29,63 +201,104 @@ impl MigrationKind {
     }
 }
 
-#[cfg(any(unix, windows))]
-mod inner_unix_or_windows {
-    extern crate time;
+#[cfg(unix)]
+mod inner {
+    extern crate atty;
+    extern crate libc;
+
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, ExitStatus};
+    use std::time::Duration;
+    use std::{env, fs, io};
+    use std::os::unix::ffi::OsStrExt;
+    use error::{MainError, Blame};
+    use super::{MigrationKind, Watchdog};
 
     /**
-    Gets the current system time, in milliseconds since the UNIX epoch.
+    Get a directory suitable for storing user- and machine-specific data which may or may not be persisted across sessions.
+
+    This follows the XDG base directory specification: `$XDG_CACHE_HOME/cargo-script`, or `$HOME/.cache/cargo-script` if that isn't set.  On macOS, `$HOME/Library/Caches/Cargo` is used instead.  If the location used by older versions already contains a `script-cache` and/or `binary-cache`, that is used instead so that existing caches aren't orphaned.
     */
-    pub fn current_time() -> u64 {
-        /*
-        This is kinda dicey, since *ideally* both this function and `file_last_modified` would be using the same underlying APIs.  They are not, insofar as I know.
+    pub fn get_cache_dir() -> Result<PathBuf, MainError> {
+        if let Ok(legacy) = get_legacy_dir() {
+            if legacy.join("script-cache").exists() || legacy.join("binary-cache").exists() {
+                return Ok(legacy);
+            }
+        }
 
-        At least, not when targetting Windows.
+        match get_default_cache_dir() {
+            Some(dir) => Ok(dir),
+            None => get_legacy_dir(),
+        }
+    }
 
-        That said, so long as everything is in the same units and uses the same epoch, it should be fine.
-        */
-        let now_1970_utc = time::now_utc().to_timespec();
-        if now_1970_utc.sec < 0 || now_1970_utc.nsec < 0 {
-            // Fuck it.
-            return 0
+    /**
+    Get a directory suitable for storing user-specific configuration data.
+
+    This follows the XDG base directory specification: `$XDG_CONFIG_HOME/cargo-script`, or `$HOME/.config/cargo-script` if that isn't set.  On macOS, `$HOME/Library/Application Support/Cargo` is used instead.  As with the cache directory, an existing `script-templates` directory in the old location takes precedence.
+    */
+    pub fn get_config_dir() -> Result<PathBuf, MainError> {
+        if let Ok(legacy) = get_legacy_dir() {
+            if legacy.join("script-templates").exists() {
+                return Ok(legacy);
+            }
+        }
+
+        match get_default_config_dir() {
+            Some(dir) => Ok(dir),
+            None => get_legacy_dir(),
         }
-        let now_ms_1970_utc = (now_1970_utc.sec as u64 * 1000)
-            + (now_1970_utc.nsec as u64 / 1_000_000);
-        now_ms_1970_utc
     }
-}
 
-#[cfg(unix)]
-mod inner {
-    extern crate atty;
+    #[cfg(not(target_os = "macos"))]
+    fn get_default_cache_dir() -> Option<PathBuf> {
+        get_xdg_dir("XDG_CACHE_HOME", ".cache")
+    }
 
-    pub use super::inner_unix_or_windows::current_time;
+    #[cfg(not(target_os = "macos"))]
+    fn get_default_config_dir() -> Option<PathBuf> {
+        get_xdg_dir("XDG_CONFIG_HOME", ".config")
+    }
 
-    use std::path::{Path, PathBuf};
-    use std::{cmp, env, fs, io};
-    use std::os::unix::ffi::OsStrExt;
-    use std::os::unix::fs::MetadataExt;
-    use error::{MainError, Blame};
-    use super::MigrationKind;
+    /*
+    macOS has its own conventions, which the XDG variables don't really fit into.
+    */
+    #[cfg(target_os = "macos")]
+    fn get_default_cache_dir() -> Option<PathBuf> {
+        env::var_os("HOME")
+            .map(|home| Path::new(&home).join("Library").join("Caches").join("Cargo"))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn get_default_config_dir() -> Option<PathBuf> {
+        env::var_os("HOME")
+            .map(|home| Path::new(&home).join("Library").join("Application Support").join("Cargo"))
+    }
 
     /**
-    Gets the last-modified time of a file, in milliseconds since the UNIX epoch.
+    Works out the `cargo-script` subdirectory of an XDG base directory.
+
+    `var` names the environment variable to check first; `home_rel` is the fallback relative to `$HOME`.  The spec says relative paths in these variables are invalid and should be ignored, so we do.
     */
-    pub fn file_last_modified(file: &fs::File) -> u64 {
-        let mtime_s_1970_utc = file.metadata()
-            .map(|md| md.mtime())
-            .unwrap_or(0);
+    #[cfg(not(target_os = "macos"))]
+    fn get_xdg_dir(var: &str, home_rel: &str) -> Option<PathBuf> {
+        if let Some(dir) = env::var_os(var) {
+            let dir = Path::new(&dir);
+            if dir.is_absolute() {
+                return Some(dir.join("cargo-script"));
+            }
+        }
 
-        let mtime_s_1970_utc = cmp::max(0, mtime_s_1970_utc);
-        mtime_s_1970_utc as u64 * 1000
+        env::var_os("HOME")
+            .map(|home| Path::new(&home).join(home_rel).join("cargo-script"))
     }
 
     /**
-    Get a directory suitable for storing user- and machine-specific data which may or may not be persisted across sessions.
+    Get the directory used for both cache and configuration data prior to XDG support.
 
     This is chosen to match the location where Cargo places its cache data.
     */
-    pub fn get_cache_dir() -> Result<PathBuf, MainError> {
+    fn get_legacy_dir() -> Result<PathBuf, MainError> {
         // try $CARGO_HOME then fall back to $HOME
         if let Some(home) = env::var_os("CARGO_HOME") {
             let home = Path::new(&home);
@@ -109,16 +322,6 @@ mod inner {
         Err((Blame::Human, "neither $CARGO_HOME nor $HOME is defined").into())
     }
 
-    /**
-    Get a directory suitable for storing user-specific configuration data.
-
-    This is chosen to match the location where Cargo places its configuration data.
-    */
-    pub fn get_config_dir() -> Result<PathBuf, MainError> {
-        // Currently, this appears to be the same as the cache directory.
-        get_cache_dir()
-    }
-
     pub fn migrate_old_data(kind: MigrationKind) -> (Vec<String>, Result<(), MainError>) {
         let mut log = vec![];
         match migrate_0_2_0(kind, &mut log) {
@@ -132,73 +335,111 @@ mod inner {
         /*
         Previously, when `CARGO_HOME` was defined on !Windows, the cache would be at `$CARGO_HOME/.cargo`.  If it exists, its contents (`script-cache` and `binary-cache`) need to moved into `$CARGO_HOME` directly.
         */
-        if let Some(home) = env::var_os("CARGO_HOME") {
-            let home = Path::new(&home);
-            let old_base = home.join(".cargo");
-            if old_base.exists() {
-                info!("<0.2.0 cache directory ({:?}) exists; attempting migration", old_base);
-
-                /*
-                Why both `info!` and `log`?  One for *before* we try (to help debug any issues) that only appears in the "real" log, and one for the user to let them know what we did/didn't do.
-                */
-
-                let old_script_cache = old_base.join("script-cache");
-                let new_script_cache = home.join("script-cache");
-                match (old_script_cache.exists(), new_script_cache.exists()) {
-                    (true, true) => {
-                        info!("not migrating {:?}; already exists at new location", old_script_cache);
-                        log.push(format!("Did not move {:?}: new location {:?} already exists.", old_script_cache, new_script_cache));
-                    },
-                    (true, false) => {
-                        info!("migrating {:?} -> {:?}", old_script_cache, new_script_cache);
-                        if kind.for_real() {
-                            try!(fs::rename(&old_script_cache, &new_script_cache));
-                        }
-                        log.push(format!("Moved {:?} to {:?}.", old_script_cache, new_script_cache));
-                    },
-                    (false, _) => {
-                        info!("not migrating {:?}; does not exist", old_script_cache);
-                    },
-                }
+        match env::var_os("CARGO_HOME") {
+            Some(home) => migrate_0_2_0_in(Path::new(&home), kind, log),
+            None => Ok(()),
+        }
+    }
 
-                let old_binary_cache = old_base.join("binary-cache");
-                let new_binary_cache = home.join("binary-cache");
-                match (old_binary_cache.exists(), new_binary_cache.exists()) {
-                    (true, true) => {
-                        info!("not migrating {:?}; already exists at new location", old_binary_cache);
-                        log.push(format!("Did not move {:?}: new location {:?} already exists.", old_binary_cache, new_binary_cache));
-                    },
-                    (true, false) => {
-                        info!("migrating {:?} -> {:?}", old_binary_cache, new_binary_cache);
-                        if kind.for_real() {
-                            try!(fs::rename(&old_binary_cache, &new_binary_cache));
-                        }
-                        log.push(format!("Moved {:?} to {:?}.", old_script_cache, new_script_cache));
-                    },
-                    (false, _) => {
-                        info!("not migrating {:?}; does not exist", old_binary_cache);
-                    },
-                }
+    fn migrate_0_2_0_in(home: &Path, kind: MigrationKind, log: &mut Vec<String>) -> Result<(), MainError> {
+        let old_base = home.join(".cargo");
+        if old_base.exists() {
+            info!("<0.2.0 cache directory ({:?}) exists; attempting migration", old_base);
+
+            /*
+            Why both `info!` and `log`?  One for *before* we try (to help debug any issues) that only appears in the "real" log, and one for the user to let them know what we did/didn't do.
+            */
+
+            let old_script_cache = old_base.join("script-cache");
+            let new_script_cache = home.join("script-cache");
+            match (old_script_cache.exists(), new_script_cache.exists()) {
+                (true, true) => {
+                    info!("not migrating {:?}; already exists at new location", old_script_cache);
+                    log.push(format!("Did not move {:?}: new location {:?} already exists.", old_script_cache, new_script_cache));
+                },
+                (true, false) => {
+                    info!("migrating {:?} -> {:?}", old_script_cache, new_script_cache);
+                    if kind.for_real() {
+                        try!(fs::rename(&old_script_cache, &new_script_cache));
+                    }
+                    log.push(format!("Moved {:?} to {:?}.", old_script_cache, new_script_cache));
+                },
+                (false, _) => {
+                    info!("not migrating {:?}; does not exist", old_script_cache);
+                },
+            }
 
-                // If `$CARGO_HOME/.cargo` is empty, remove it.
-                if try!(fs::read_dir(&old_base)).next().is_none() {
-                    info!("{:?} is empty; removing", old_base);
+            let old_binary_cache = old_base.join("binary-cache");
+            let new_binary_cache = home.join("binary-cache");
+            match (old_binary_cache.exists(), new_binary_cache.exists()) {
+                (true, true) => {
+                    info!("not migrating {:?}; already exists at new location", old_binary_cache);
+                    log.push(format!("Did not move {:?}: new location {:?} already exists.", old_binary_cache, new_binary_cache));
+                },
+                (true, false) => {
+                    info!("migrating {:?} -> {:?}", old_binary_cache, new_binary_cache);
                     if kind.for_real() {
-                        try!(fs::remove_dir(&old_base));
+                        try!(fs::rename(&old_binary_cache, &new_binary_cache));
                     }
-                    log.push(format!("Removed empty directory {:?}", old_base));
-                } else {
-                    info!("not removing {:?}; not empty", old_base);
-                    log.push(format!("Not removing {:?}: not empty.", old_base));
-                }
+                    log.push(format!("Moved {:?} to {:?}.", old_binary_cache, new_binary_cache));
+                },
+                (false, _) => {
+                    info!("not migrating {:?}; does not exist", old_binary_cache);
+                },
+            }
 
-                info!("done with migration");
+            // If `$CARGO_HOME/.cargo` is empty, remove it.
+            if try!(fs::read_dir(&old_base)).next().is_none() {
+                info!("{:?} is empty; removing", old_base);
+                if kind.for_real() {
+                    try!(fs::remove_dir(&old_base));
+                }
+                log.push(format!("Removed empty directory {:?}", old_base));
+            } else {
+                info!("not removing {:?}; not empty", old_base);
+                log.push(format!("Not removing {:?}: not empty.", old_base));
             }
+
+            info!("done with migration");
         }
 
         Ok(())
     }
 
+    #[test]
+    fn test_migrate_0_2_0() {
+        extern crate tempdir;
+
+        let home = tempdir::TempDir::new("cargo-script-test").unwrap();
+        let home = home.path();
+        for dir in &[".cargo/script-cache", ".cargo/binary-cache"] {
+            fs::create_dir_all(home.join(dir)).unwrap();
+        }
+
+        let moved = |cache: &str| format!("Moved {:?} to {:?}.",
+            home.join(".cargo").join(cache), home.join(cache));
+
+        let mut log = vec![];
+        migrate_0_2_0_in(home, MigrationKind::DryRun, &mut log).unwrap();
+        assert_eq!(log, vec![
+            moved("script-cache"),
+            moved("binary-cache"),
+            format!("Not removing {:?}: not empty.", home.join(".cargo")),
+        ]);
+        assert!(home.join(".cargo/binary-cache").exists());
+
+        let mut log = vec![];
+        migrate_0_2_0_in(home, MigrationKind::ForReal, &mut log).unwrap();
+        assert_eq!(log, vec![
+            moved("script-cache"),
+            moved("binary-cache"),
+            format!("Removed empty directory {:?}", home.join(".cargo")),
+        ]);
+        assert!(home.join("script-cache").exists());
+        assert!(home.join("binary-cache").exists());
+        assert!(!home.join(".cargo").exists());
+    }
+
     pub fn write_path<W>(w: &mut W, path: &Path) -> io::Result<()>
     where W: io::Write {
         w.write_all(path.as_os_str().as_bytes())
@@ -212,6 +453,13 @@ mod inner {
         Ok(OsStr::from_bytes(&buf).into())
     }
 
+    /**
+    Paths aren't length-limited in any way we can do something about, so this does nothing.
+    */
+    pub fn extended_length_path(path: PathBuf) -> PathBuf {
+        path
+    }
+
     /**
     Returns `true` if `cargo-script` should force Cargo to use coloured output.
 
@@ -220,27 +468,91 @@ mod inner {
     pub fn force_cargo_color() -> bool {
         atty::is(atty::Stream::Stderr)
     }
+
+    /**
+    Creates a command that runs `cmd` with the system shell.
+    */
+    pub fn shell_command(cmd: &str) -> Command {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(cmd);
+        shell
+    }
+
+    /**
+    Turns the exit status of a child process into the code we should exit with.
+
+    If the child was killed by a signal, this follows the shell convention of 128 plus the signal number.
+    */
+    pub fn exit_status_code(st: ExitStatus) -> i32 {
+        use std::os::unix::process::ExitStatusExt;
+        match (st.code(), st.signal()) {
+            (Some(code), _) => code,
+            (None, Some(sig)) => 128 + sig,
+            (None, None) => 1,
+        }
+    }
+
+    /**
+    Runs a command to completion, killing it if it takes longer than `timeout`.
+
+    Normally, the command is started in its own process group, and the whole group is killed, so that anything the command itself started goes too.  Returns `None` if the command was killed.
+
+    If standard input is a terminal, though, the command stays in *our* process group, as with `timeout --foreground`.  A new group wouldn't be the terminal's foreground group, so Ctrl+C would kill us but leave the command running, and the command would be stopped the moment it tried to read from the terminal.  The price is that only the command itself is killed when the time runs out, not anything it started.
+    */
+    pub fn status_with_timeout(cmd: &mut Command, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+        use std::os::unix::process::CommandExt;
+
+        let own_group = unsafe { libc::isatty(libc::STDIN_FILENO) } == 0;
+        if own_group {
+            unsafe {
+                cmd.before_exec(|| {
+                    if libc::setpgid(0, 0) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        let mut child = try!(cmd.spawn());
+        let pid = child.id() as libc::pid_t;
+
+        let watchdog = Watchdog::start(timeout, move || {
+            if own_group {
+                info!("timed out; killing process group {}", pid);
+                unsafe { libc::kill(-pid, libc::SIGKILL); }
+            } else {
+                info!("timed out; killing process {}", pid);
+                unsafe { libc::kill(pid, libc::SIGKILL); }
+            }
+        });
+
+        let st = child.wait();
+        let timed_out = watchdog.finish();
+        let st = try!(st);
+        Ok(if timed_out { None } else { Some(st) })
+    }
 }
 
 #[cfg(windows)]
 pub mod inner {
     #![allow(non_snake_case)]
 
+    extern crate kernel32;
     extern crate ole32;
     extern crate shell32;
     extern crate winapi;
 
-    pub use super::inner_unix_or_windows::current_time;
-
-    use std::ffi::OsString;
+    use std::ffi::{OsStr, OsString};
     use std::fmt;
-    use std::fs;
-    use std::io;
-    use std::path::{Path, PathBuf};
-    use std::mem;
+    use std::{env, fs, io};
+    use std::path::{Component, Path, PathBuf, Prefix};
+    use std::process::{Command, ExitStatus};
+    use std::ptr;
+    use std::time::Duration;
     use std::os::windows::ffi::{OsStrExt, OsStringExt};
     use error::MainError;
-    use super::MigrationKind;
+    use super::{MigrationKind, Watchdog};
 
     #[cfg(old_rustc_windows_linking_behaviour)]
     mod uuid {
@@ -274,24 +586,6 @@ pub mod inner {
         }
     }
 
-    /**
-    Gets the last-modified time of a file, in milliseconds since the UNIX epoch.
-    */
-    pub fn file_last_modified(file: &fs::File) -> u64 {
-        use ::std::os::windows::fs::MetadataExt;
-
-        const MS_BETWEEN_1601_1970: u64 = 11_644_473_600_000;
-
-        let mtime_100ns_1601_utc = file.metadata()
-            .map(|md| md.last_write_time())
-            .unwrap_or(0);
-        let mtime_ms_1601_utc = mtime_100ns_1601_utc / (1000*10);
-
-        // This can obviously underflow... but since files created prior to 1970 are going to be *somewhat rare*, I'm just going to saturate to zero.
-        let mtime_ms_1970_utc = mtime_ms_1601_utc.saturating_sub(MS_BETWEEN_1601_1970);
-        mtime_ms_1970_utc
-    }
-
     /**
     Get a directory suitable for storing user- and machine-specific data which may or may not be persisted across sessions.
 
@@ -329,23 +623,44 @@ pub mod inner {
     }
 
     fn SHGetKnownFolderPath(rfid: &winapi::KNOWNFOLDERID, dwFlags: winapi::DWORD, hToken: winapi::HANDLE) -> WinResult<OsString> {
-        use self::winapi::PWSTR;
-        let mut psz_path: PWSTR = unsafe { mem::uninitialized() };
-        let hresult = unsafe {
-            shell32::SHGetKnownFolderPath(
-                rfid,
-                dwFlags,
-                hToken,
-                mem::transmute(&mut psz_path as &mut PWSTR as *mut PWSTR)
-            )
-        };
+        known_folder_path(|psz_path| unsafe {
+            shell32::SHGetKnownFolderPath(rfid, dwFlags, hToken, psz_path)
+        })
+    }
 
-        if hresult == winapi::S_OK {
-            let r = unsafe { pwstr_to_os_string(psz_path) };
-            unsafe { ole32::CoTaskMemFree(psz_path as *mut _) };
-            Ok(r)
+    /**
+    Calls `get`, which must behave like `SHGetKnownFolderPath`, and takes ownership of the path it returns.
+
+    The out pointer starts off null, so there's never anything uninitialised to read or free.  The caller is supposed to free it whether or not the call succeeded, so we do that, too.
+    */
+    fn known_folder_path<F>(get: F) -> WinResult<OsString>
+    where F: FnOnce(*mut winapi::PWSTR) -> winapi::HRESULT {
+        let mut psz_path: winapi::PWSTR = ::std::ptr::null_mut();
+        let hresult = get(&mut psz_path as *mut _);
+
+        let r = if hresult == winapi::S_OK && !psz_path.is_null() {
+            Ok(unsafe { pwstr_to_os_string(psz_path) })
         } else {
             Err(WinError(hresult))
+        };
+
+        if !psz_path.is_null() {
+            unsafe { ole32::CoTaskMemFree(psz_path as *mut _) };
+        }
+        r
+    }
+
+    #[test]
+    fn test_known_folder_path() {
+        let rfid = unsafe { uuid::local_app_data() };
+        let dir = SHGetKnownFolderPath(rfid, 0, ::std::ptr::null_mut()).ok()
+            .expect("could not get LocalAppData");
+        assert!(Path::new(&dir).is_absolute());
+
+        // A failure which never touches the out pointer.
+        match known_folder_path(|_| winapi::E_FAIL) {
+            Err(WinError(hresult)) => assert_eq!(hresult, winapi::E_FAIL),
+            Ok(path) => panic!("expected failure, got {:?}", path),
         }
     }
 
@@ -368,6 +683,84 @@ pub mod inner {
         (vec![], Ok(()))
     }
 
+    /**
+    Converts a path into its `\\?\` extended-length form, which isn't subject to `MAX_PATH`, regardless of whether long paths have been enabled system-wide.
+
+    If the path exists, it's canonicalised, which gives the extended-length form anyway.  Otherwise, it's made absolute and the `\\?\` prefix added by hand; since Windows won't interpret `.` or `..` in such a path, those are resolved first.  Paths already in extended-length form, and anything we don't understand, are returned unchanged.
+    */
+    pub fn extended_length_path(path: PathBuf) -> PathBuf {
+        if let Ok(canon) = fs::canonicalize(&path) {
+            return canon;
+        }
+
+        let path = if path.is_absolute() {
+            path
+        } else {
+            match env::current_dir() {
+                Ok(cwd) => cwd.join(path),
+                Err(_) => return path,
+            }
+        };
+
+        match verbatim_path(&path) {
+            Some(verbatim) => verbatim.into(),
+            None => path,
+        }
+    }
+
+    fn verbatim_path(path: &Path) -> Option<OsString> {
+        let mut out = OsString::new();
+        let mut parts: Vec<&OsStr> = vec![];
+
+        for comp in path.components() {
+            match comp {
+                Component::Prefix(prefix) => match prefix.kind() {
+                    Prefix::Disk(_) => {
+                        out.push(r"\\?\");
+                        out.push(prefix.as_os_str());
+                    },
+                    Prefix::UNC(server, share) => {
+                        out.push(r"\\?\UNC\");
+                        out.push(server);
+                        out.push(r"\");
+                        out.push(share);
+                    },
+                    _ => return None,
+                },
+                Component::RootDir | Component::CurDir => (),
+                Component::ParentDir => { parts.pop(); },
+                Component::Normal(part) => parts.push(part),
+            }
+        }
+
+        if out.is_empty() {
+            return None;
+        }
+        if parts.is_empty() {
+            out.push(r"\");
+        }
+        for part in parts {
+            out.push(r"\");
+            out.push(part);
+        }
+        Some(out)
+    }
+
+    #[test]
+    fn test_extended_length_path() {
+        let elp = |s: &str| extended_length_path(s.into()).into_os_string().into_string().unwrap();
+
+        assert_eq!(elp(r"Z:\no such dir\.\cache\..\Cargo"), r"\\?\Z:\no such dir\Cargo");
+        assert_eq!(elp(r"\\no-such-server\share\Cargo"), r"\\?\UNC\no-such-server\share\Cargo");
+        assert_eq!(elp(r"\\?\Z:\no such dir\..\Cargo"), r"\\?\Z:\no such dir\..\Cargo");
+
+        assert_eq!(verbatim_path(Path::new(r"Z:\")), Some(r"\\?\Z:\".into()));
+        assert_eq!(verbatim_path(Path::new(r"\\?\Z:\Cargo")), None);
+
+        let tmp = env::temp_dir();
+        assert!(elp(tmp.to_str().unwrap()).starts_with(r"\\?\"));
+    }
+
     pub fn write_path<W>(w: &mut W, path: &Path) -> io::Result<()>
     where W: io::Write {
         for word in path.as_os_str().encode_wide() {
@@ -383,16 +776,34 @@ pub mod inner {
         let mut buf = vec![];
         try!(r.read_to_end(&mut buf));
 
-        let mut words = Vec::with_capacity(buf.len() / 2);
-        let mut it = buf.iter().cloned();
-        while let Some(lo) = it.next() {
-            let hi = it.next().unwrap();
-            words.push(lo as u16 | ((hi as u16) << 8));
+        // Paths are written as whole UTF-16 words, so anything else means the file is damaged.
+        if buf.len() % 2 != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("stored path has an odd number of bytes ({})", buf.len())));
         }
 
+        let words: Vec<u16> = buf.chunks(2)
+            .map(|w| w[0] as u16 | ((w[1] as u16) << 8))
+            .collect();
+
         return Ok(OsString::from_wide(&words).into())
     }
 
+    #[test]
+    fn test_read_write_path() {
+        for path in &[r"C:\Users\Me\AppData\Local\Cargo", r"\\?\C:\Users\Me\AppData\Local\Cargo", r"\\?\UNC\server\share\Cargo"] {
+            let mut buf = vec![];
+            write_path(&mut buf, Path::new(path)).unwrap();
+            assert_eq!(read_path(&mut &buf[..]).unwrap(), Path::new(path));
+        }
+    }
+
+    #[test]
+    fn test_read_path_odd_length() {
+        let err = read_path(&mut &b"C\0:"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
     /**
     Returns `true` if `cargo-script` should force Cargo to use coloured output.
 
@@ -401,4 +812,68 @@ pub mod inner {
     pub fn force_cargo_color() -> bool {
         false
     }
+
+    /**
+    Creates a command that runs `cmd` with the system shell.
+    */
+    pub fn shell_command(cmd: &str) -> Command {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(cmd);
+        shell
+    }
+
+    /**
+    Turns the exit status of a child process into the code we should exit with.
+
+    On Windows, processes always have an exit code.
+    */
+    pub fn exit_status_code(st: ExitStatus) -> i32 {
+        st.code().unwrap_or(1)
+    }
+
+    /**
+    Owns a job object handle, closing it when dropped.
+    */
+    struct Job(winapi::HANDLE);
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            unsafe { kernel32::CloseHandle(self.0); }
+        }
+    }
+
+    /**
+    Runs a command to completion, killing it if it takes longer than `timeout`.
+
+    The command is put in a job object, and the whole job is terminated, so that anything the command itself started goes too.  Returns `None` if the command was killed.
+    */
+    pub fn status_with_timeout(cmd: &mut Command, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+        use std::os::windows::io::AsRawHandle;
+
+        let job = unsafe { kernel32::CreateJobObjectW(ptr::null_mut(), ptr::null()) };
+        if job.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let job = Job(job);
+
+        let mut child = try!(cmd.spawn());
+        if unsafe { kernel32::AssignProcessToJobObject(job.0, child.as_raw_handle() as winapi::HANDLE) } == 0 {
+            let err = io::Error::last_os_error();
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+
+        // Handles aren't `Send`, so smuggle it across as an integer.  The watchdog is finished before the `Job` is dropped, so it can't use the handle after it's closed.
+        let job_addr = job.0 as usize;
+        let watchdog = Watchdog::start(timeout, move || {
+            info!("timed out; terminating job");
+            unsafe { kernel32::TerminateJobObject(job_addr as winapi::HANDLE, 1); }
+        });
+
+        let st = child.wait();
+        let timed_out = watchdog.finish();
+        let st = try!(st);
+        Ok(if timed_out { None } else { Some(st) })
+    }
 }
@@ -28,15 +28,22 @@ lazy_static! {
 #[derive(Debug)]
 pub enum Args {
     Dump { name: String },
-    List,
+    List { show_source: bool },
+    Rename { old: String, new: String, force: bool, dry_run: bool },
     Show { path: bool },
+    Validate { name: String },
 }
 
+/**
+Everything the subcommand can be called; just its name, since it has no aliases.
+*/
+pub const NAMES: &'static [&'static str] = &["templates"];
+
 impl Args {
     pub fn subcommand() -> clap::App<'static, 'static> {
         use clap::{AppSettings, Arg, SubCommand};
 
-        SubCommand::with_name("templates")
+        SubCommand::with_name(NAMES[0])
             .about("Manage Cargo Script expression templates.")
             .setting(AppSettings::SubcommandRequiredElseHelp)
 
@@ -52,6 +59,34 @@ impl Args {
 
             .subcommand(SubCommand::with_name("list")
                 .about("List the available templates.")
+
+                .arg(Arg::with_name("show_source")
+                    .help("Also list the built-in templates, and show whether each template is built-in or user-defined.")
+                    .long("show-source")
+                )
+            )
+
+            .subcommand(SubCommand::with_name("rename")
+                .about("Rename a template.")
+
+                .arg(Arg::with_name("old")
+                    .help("Name of the template to rename.")
+                    .index(1)
+                    .required(true)
+                )
+                .arg(Arg::with_name("new")
+                    .help("New name for the template.")
+                    .index(2)
+                    .required(true)
+                )
+                .arg(Arg::with_name("force")
+                    .help("Replace an existing template, or override a built-in template.")
+                    .long("force")
+                )
+                .arg(Arg::with_name("dry_run")
+                    .help("Report what would be renamed without actually renaming anything.")
+                    .long("dry-run")
+                )
             )
 
             .subcommand(SubCommand::with_name("show")
@@ -62,6 +97,16 @@ impl Args {
                     .long("path")
                 )
             )
+
+            .subcommand(SubCommand::with_name("validate")
+                .about("Check that a template uses only known substitutions, and includes the script.")
+
+                .arg(Arg::with_name("template")
+                    .help("Name of template to validate.")
+                    .index(1)
+                    .required(true)
+                )
+            )
     }
 
     pub fn parse(m: &clap::ArgMatches) -> Self {
@@ -71,12 +116,29 @@ impl Args {
                     name: m.value_of("template").unwrap().into(),
                 }
             },
-            ("list", _) => Args::List,
+            ("list", Some(m)) => {
+                Args::List {
+                    show_source: m.is_present("show_source"),
+                }
+            },
+            ("rename", Some(m)) => {
+                Args::Rename {
+                    old: m.value_of("old").unwrap().into(),
+                    new: m.value_of("new").unwrap().into(),
+                    force: m.is_present("force"),
+                    dry_run: m.is_present("dry_run"),
+                }
+            },
             ("show", Some(m)) => {
                 Args::Show {
                     path: m.is_present("show_path"),
                 }
             },
+            ("validate", Some(m)) => {
+                Args::Validate {
+                    name: m.value_of("template").unwrap().into(),
+                }
+            },
             (name, _) => panic!("bad subcommand: {:?}", name)
         }
     }
@@ -85,8 +147,10 @@ impl Args {
 pub fn try_main(args: Args) -> Result<i32> {
     match args {
         Args::Dump { name } => try!(dump(&name)),
-        Args::List => try!(list()),
+        Args::List { show_source } => try!(list(show_source)),
+        Args::Rename { old, new, force, dry_run } => try!(rename(&old, &new, force, dry_run)),
         Args::Show { path } => try!(show(path)),
+        Args::Validate { name } => try!(validate(&name)),
     }
 
     Ok(0)
@@ -124,6 +188,38 @@ pub fn expand(src: &str, subs: &HashMap<&str, &str>) -> Result<String> {
     Ok(result)
 }
 
+/**
+Does the template use substitution `name` anywhere?
+*/
+pub fn uses_sub(src: &str, name: &str) -> bool {
+    RE_SUB.captures_iter(src).any(|m| m.get(1).unwrap().as_str() == name)
+}
+
+/**
+Works out which line of the expanded template the first use of substitution `name` starts on, counting from 1.  Returns `None` if the template doesn't use it at all.
+*/
+pub fn sub_line(src: &str, subs: &HashMap<&str, &str>, name: &str) -> Result<Option<usize>> {
+    let start = match RE_SUB.captures_iter(src).find(|m| m.get(1).unwrap().as_str() == name) {
+        Some(m) => m.get(0).unwrap().start(),
+        None => return Ok(None),
+    };
+    let prior = try!(expand(&src[..start], subs));
+    Ok(Some(prior.matches('\n').count() + 1))
+}
+
+#[test]
+fn test_sub_line() {
+    let mut subs = HashMap::new();
+    subs.insert("script", "x\ny");
+    subs.insert("prelude", "a\nb\n");
+
+    assert_eq!(sub_line("#{script}", &subs, "script").ok(), Some(Some(1)));
+    assert_eq!(sub_line("\n\n  {#{script}}", &subs, "script").ok(), Some(Some(3)));
+    assert_eq!(sub_line("#{prelude}\n#{script}\n#{script}", &subs, "script").ok(), Some(Some(4)));
+    assert_eq!(sub_line("#{prelude}", &subs, "script").ok(), Some(None));
+    assert!(sub_line("#{bogus}\n#{script}", &subs, "script").is_err());
+}
+
 /**
 Returns the path to the template directory.
 */
@@ -165,9 +261,29 @@ pub fn get_template(name: &str) -> Result<Cow<'static, str>> {
 
     let mut text = String::new();
     try!(file.read_to_string(&mut text));
+
+    /*
+    A copy of a built-in template will quietly go stale when the built-in changes, so point out ones that are still identical.  There's no harm in using the built-in instead.
+    */
+    if let Some(builtin) = builtin_template(name) {
+        if text.trim_right() == builtin.trim_right() {
+            use std::io::Write;
+            let _ = writeln!(::std::io::stderr(),
+                "warning: template `{}.rs` in {} is identical to the built-in one; consider removing it so it doesn't go stale",
+                name, base.display());
+            return Ok(builtin.into());
+        }
+        info!("template {:?} overrides the built-in", name);
+    }
+
     Ok(text.into())
 }
 
+/**
+Names of all the built-in templates.
+*/
+const BUILTIN_TEMPLATES: &'static [&'static str] = &["expr", "file", "loop", "loop-count"];
+
 fn builtin_template(name: &str) -> Option<&'static str> {
     Some(match name {
         "expr" => consts::EXPR_TEMPLATE,
@@ -184,8 +300,9 @@ fn dump(name: &str) -> Result<()> {
     Ok(())
 }
 
-fn list() -> Result<()> {
+fn list(show_source: bool) -> Result<()> {
     use std::ffi::OsStr;
+    use std::io::Read;
 
     let t_path = try!(get_template_path());
 
@@ -197,6 +314,7 @@ fn list() -> Result<()> {
         return Err(format!("cannot list template directory `{}`: it is not a directory", t_path.display()).into());
     }
 
+    let mut names = vec![];
     for entry in try!(fs::read_dir(&t_path)) {
         let entry = try!(entry);
         if !try!(entry.file_type()).is_file() {
@@ -207,9 +325,77 @@ fn list() -> Result<()> {
             continue;
         }
         if let Some(stem) = f_path.file_stem() {
-            println!("{}", stem.to_string_lossy());
+            names.push((stem.to_string_lossy().into_owned(), Some(f_path.clone())));
         }
     }
+
+    if show_source {
+        for &name in BUILTIN_TEMPLATES {
+            if !names.iter().any(|&(ref n, _)| n == name) {
+                names.push((name.into(), None));
+            }
+        }
+    }
+    names.sort();
+
+    for (name, path) in names {
+        let note = match (builtin_template(&name), path) {
+            (Some(builtin), Some(path)) => {
+                let mut text = String::new();
+                try!(try!(fs::File::open(&path)).read_to_string(&mut text));
+                if text.trim_right() == builtin.trim_right() {
+                    " (identical to built-in)"
+                } else {
+                    " (overrides built-in)"
+                }
+            },
+            _ => "",
+        };
+
+        if show_source {
+            let source = match builtin_template(&name) {
+                Some(_) if note == "" => "builtin",
+                _ => "user",
+            };
+            println!("{:<7} {}{}", source, name, note);
+        } else {
+            println!("{}{}", name, note);
+        }
+    }
+    Ok(())
+}
+
+fn rename(old: &str, new: &str, force: bool, dry_run: bool) -> Result<()> {
+    for name in &[old, new] {
+        if name.is_empty() || name.contains(|c| c == '/' || c == '\\') {
+            return Err((Blame::Human, format!("`{}` is not a valid template name", name)).into());
+        }
+    }
+
+    let t_path = try!(get_template_path());
+    let old_path = t_path.join(format!("{}.rs", old));
+    let new_path = t_path.join(format!("{}.rs", new));
+
+    if !old_path.is_file() {
+        return Err((Blame::Human, format!("template file `{}.rs` does not exist in {}", old, t_path.display())).into());
+    }
+
+    if !force {
+        if new_path.exists() {
+            return Err((Blame::Human, format!("template file `{}.rs` already exists in {}; use `--force` to replace it", new, t_path.display())).into());
+        }
+        if builtin_template(new).is_some() {
+            return Err((Blame::Human, format!("`{}` is a built-in template; use `--force` to override it", new)).into());
+        }
+    }
+
+    if dry_run {
+        println!("would rename {} to {}", old_path.display(), new_path.display());
+        return Ok(());
+    }
+
+    info!("renaming {:?} to {:?}", old_path, new_path);
+    try!(fs::rename(&old_path, &new_path));
     Ok(())
 }
 
@@ -231,3 +417,50 @@ fn show(path: bool) -> Result<()> {
         Ok(())
     }
 }
+
+fn validate(name: &str) -> Result<()> {
+    let text = try!(get_template(name));
+    let subs = try!(check_template(&text)
+        .err_tag(format!("template `{}` is not valid", name)));
+    println!("template `{}` uses: {}", name, subs.join(", "));
+    Ok(())
+}
+
+/**
+Checks that a template only uses known substitutions, and that it actually includes the script.  Returns the substitutions used, in order of first use.
+*/
+fn check_template(text: &str) -> Result<Vec<&str>> {
+    let known = [consts::SCRIPT_BODY_SUB, consts::SCRIPT_PRELUDE_SUB, consts::SCRIPT_BINDINGS_SUB, consts::SCRIPT_OUTPUT_SUB];
+
+    let mut used = vec![];
+    for m in RE_SUB.captures_iter(text) {
+        let sub_name = m.get(1).unwrap().as_str();
+        if !known.contains(&sub_name) {
+            return Err((Blame::Human, format!("substitution `{}` is unknown; expected one of: {}", sub_name, known.join(", "))).into());
+        }
+        if !used.contains(&sub_name) {
+            used.push(sub_name);
+        }
+    }
+
+    if !used.contains(&consts::SCRIPT_BODY_SUB) {
+        return Err((Blame::Human, format!("missing `#{{{}}}`; the script would be ignored", consts::SCRIPT_BODY_SUB)).into());
+    }
+
+    // Make sure it really does expand.
+    let subs = known.iter().map(|&k| (k, "")).collect();
+    try!(expand(text, &subs));
+
+    Ok(used)
+}
+
+#[test]
+fn test_check_template() {
+    assert_eq!(check_template("#{script}").ok(), Some(vec!["script"]));
+    assert_eq!(check_template(consts::EXPR_TEMPLATE).ok(), Some(vec!["prelude", "bindings", "script", "output"]));
+    assert_eq!(check_template("#{script} #{prelude} #{script}").ok(), Some(vec!["script", "prelude"]));
+
+    assert!(check_template("").is_err());
+    assert!(check_template("#{prelude}").is_err());
+    assert!(check_template("#{script} #{bogus}").is_err());
+}
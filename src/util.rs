@@ -10,6 +10,7 @@ or distributed except according to those terms.
 /*!
 This module just contains other random implementation stuff.
 */
+use std::borrow::Cow;
 use std::error::Error;
 use std::marker::PhantomData;
 
@@ -200,7 +201,196 @@ impl SubsliceOffset for str {
     }
 }
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/**
+Expands a leading `~` in a path to the user's home directory, as a shell would.
+
+Only a bare `~`, or one followed by a path separator, is expanded; `~user` and a `~` anywhere else are left alone.  If the home directory can't be found, the path is returned as-is.
+*/
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let home = ::std::env::var_os("HOME").or_else(|| ::std::env::var_os("USERPROFILE"));
+    expand_tilde_in(path, home.as_ref().map(Path::new))
+}
+
+fn expand_tilde_in(path: &str, home: Option<&Path>) -> PathBuf {
+    let home = match home {
+        Some(home) => home,
+        None => return path.into(),
+    };
+
+    if path == "~" {
+        return home.into();
+    }
+    if path.starts_with("~/") || (cfg!(windows) && path.starts_with("~\\")) {
+        return home.join(&path[2..]);
+    }
+    path.into()
+}
+
+#[test]
+fn test_expand_tilde() {
+    let home = Some(Path::new("/home/me"));
+    assert_eq!(expand_tilde_in("~", home), Path::new("/home/me"));
+    assert_eq!(expand_tilde_in("~/scripts/foo.rs", home), Path::new("/home/me/scripts/foo.rs"));
+    assert_eq!(expand_tilde_in("~other/foo.rs", home), Path::new("~other/foo.rs"));
+    assert_eq!(expand_tilde_in("scripts/~/foo.rs", home), Path::new("scripts/~/foo.rs"));
+    assert_eq!(expand_tilde_in("foo~.rs", home), Path::new("foo~.rs"));
+    assert_eq!(expand_tilde_in("~/foo.rs", None), Path::new("~/foo.rs"));
+}
+
+/**
+Works out roughly how much space a file or directory takes up, in bytes.
+
+Symbolic links are *not* followed.
+*/
+pub fn disk_usage(path: &Path) -> ::std::io::Result<u64> {
+    let md = try!(::std::fs::symlink_metadata(path));
+    if !md.is_dir() {
+        return Ok(md.len());
+    }
+
+    let mut total = 0;
+    for child in try!(::std::fs::read_dir(path)) {
+        let child = try!(child);
+        total += try!(disk_usage(&child.path()));
+    }
+    Ok(total)
+}
+
+/**
+Writes a file such that it ends up either completely written, or not changed at all.
+
+`write` is given a temporary file in the same directory, which is then renamed over `path`.  If we're interrupted part way through, all that's left is a stray temporary file, which nothing ever reads.
+*/
+pub fn write_atomic<F>(path: &Path, write: F) -> ::std::io::Result<()>
+where F: FnOnce(&mut ::std::fs::File) -> ::std::io::Result<()> {
+    use std::fs;
+    use std::io;
+
+    let file_name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("cannot write to {:?}: it has no file name", path))),
+    };
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, ::platform::current_time()));
+
+    let written = fs::File::create(&tmp_path)
+        .and_then(|mut f| {
+            try!(write(&mut f));
+            f.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp_path, path));
+
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+#[test]
+fn test_write_atomic() {
+    extern crate tempdir;
+    use std::fs;
+    use std::io::{self, Read, Write};
+
+    let dir = tempdir::TempDir::new("cargo-script-test").unwrap();
+    let path = dir.path().join("file");
+
+    write_atomic(&path, |f| f.write_all(b"first")).unwrap();
+    write_atomic(&path, |f| f.write_all(b"second")).unwrap();
+
+    // A failed write leaves the old contents, and nothing else, behind.
+    let err = write_atomic(&path, |f| {
+        try!(f.write_all(b"thi"));
+        Err(io::Error::new(io::ErrorKind::Other, "interrupted"))
+    });
+    assert!(err.is_err());
+
+    let mut s = String::new();
+    fs::File::open(&path).unwrap().read_to_string(&mut s).unwrap();
+    assert_eq!(s, "second");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+/**
+Quotes a string, if necessary, so that it can be pasted into a shell as a single argument.
+
+This targets POSIX shells, except on Windows, where it targets `cmd.exe`.
+*/
+pub fn shell_quote(s: &str) -> Cow<str> {
+    let safe = |c: char| c.is_alphanumeric() || "_-./=:,+@%".contains(c);
+    if s != "" && s.chars().all(safe) {
+        return s.into();
+    }
+
+    if cfg!(windows) {
+        format!("\"{}\"", s.replace("\"", "\\\"")).into()
+    } else {
+        format!("'{}'", s.replace("'", "'\\''")).into()
+    }
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_quote() {
+    assert_eq!(shell_quote("--manifest-path"), "--manifest-path");
+    assert_eq!(shell_quote("/tmp/cache/Cargo.toml"), "/tmp/cache/Cargo.toml");
+    assert_eq!(shell_quote(""), "''");
+    assert_eq!(shell_quote("-C opt-level=3"), "'-C opt-level=3'");
+    assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    assert_eq!(shell_quote("$HOME"), "'$HOME'");
+}
+
+/**
+Formats a size in bytes into something a human would want to read.
+*/
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &'static [&'static str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for &next_unit in &UNITS[1..] {
+        if size < 1024.0 { break }
+        size /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+/**
+Formats a duration in milliseconds as a short age, using the largest unit that fits; for example: `12h`.
+
+The units are the same as those accepted by `gc --older-than`.
+*/
+pub fn human_age(ms: u64) -> String {
+    const UNITS: &'static [(u64, &'static str)] = &[
+        (7*24*60*60*1000, "w"),
+        (24*60*60*1000, "d"),
+        (60*60*1000, "h"),
+        (60*1000, "m"),
+    ];
+
+    for &(unit_ms, unit) in UNITS {
+        if ms >= unit_ms {
+            return format!("{}{}", ms / unit_ms, unit);
+        }
+    }
+    format!("{}s", ms / 1000)
+}
+
+#[test]
+fn test_human_age() {
+    assert_eq!(human_age(0), "0s");
+    assert_eq!(human_age(59*1000), "59s");
+    assert_eq!(human_age(90*60*1000), "1h");
+    assert_eq!(human_age(3*24*60*60*1000 + 1), "3d");
+    assert_eq!(human_age(15*24*60*60*1000), "2w");
+}
 
 /**
 Stable replacement for unstable `std::fs::PathExt`.
@@ -0,0 +1,3 @@
+fn double(x: i32) -> i32 {
+    x * 2
+}
@@ -0,0 +1,16 @@
+/*!
+```cargo
+[[bin]]
+name = "first"
+path = "script-bins.rs"
+
+[[bin]]
+name = "second"
+path = "script-bins.rs"
+```
+*/
+fn main() {
+    let exe = std::env::current_exe().unwrap();
+    println!("--output--");
+    println!("running {}", exe.file_stem().unwrap().to_string_lossy());
+}
@@ -0,0 +1,4 @@
+#[no_mangle]
+pub extern "C" fn cargo_script_add(a: i32, b: i32) -> i32 {
+    a + b
+}
@@ -0,0 +1,4 @@
+// This file has no `fn main() {}` of its own, so it's treated as an expression.
+println!("--output--");
+let x = Some(1);
+x
@@ -0,0 +1,11 @@
+/*!
+```cargo
+[profile.checked]
+inherits = "release"
+debug-assertions = true
+```
+*/
+fn main() {
+    println!("--output--");
+    println!("debug assertions: {}", cfg!(debug_assertions));
+}
@@ -0,0 +1,7 @@
+#[test]
+fn passes() {}
+
+#[test]
+fn fails() {
+    panic!("this test should have been filtered out");
+}
@@ -0,0 +1,53 @@
+use cargo_script::{build_script, run_cached_script, run_script, Options, ScriptInput};
+use util::{Output, CARGO_MUTEX, OUTPUT_MARKER_CODE};
+
+#[test]
+fn test_api_expr() {
+    let _lock = CARGO_MUTEX.lock().expect("could not acquire Cargo mutex");
+    let out: Output = run_script(ScriptInput::Expr(with_output_marker!("1 + 2")), &Options::default())
+        .unwrap().into();
+    scan!(out.stdout_output();
+        ("3") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_api_file() {
+    let _lock = CARGO_MUTEX.lock().expect("could not acquire Cargo mutex");
+    let out: Output = run_script(ScriptInput::File("tests/data/script-no-deps.rs".into()), &Options::default())
+        .unwrap().into();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_api_source() {
+    let _lock = CARGO_MUTEX.lock().expect("could not acquire Cargo mutex");
+    let source = format!("fn main() {{ {} println!(\"{{}}\", std::env::args().nth(1).unwrap()); }}",
+        OUTPUT_MARKER_CODE);
+    let opts = Options {
+        args: vec!["hi".into()],
+        ..Default::default()
+    };
+    let out: Output = run_script(ScriptInput::Source(source), &opts).unwrap().into();
+    scan!(out.stdout_output();
+        ("hi") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_api_build_then_run_cached() {
+    let _lock = CARGO_MUTEX.lock().expect("could not acquire Cargo mutex");
+
+    // Something that can't already be in the cache from an earlier run.
+    let nonce = ::std::time::SystemTime::now()
+        .duration_since(::std::time::UNIX_EPOCH).unwrap().subsec_nanos();
+    let input = || ScriptInput::Expr(format!("{}{}", OUTPUT_MARKER_CODE, nonce));
+
+    assert!(run_cached_script(input(), &Options::default()).is_err());
+
+    build_script(input(), &Options::default()).unwrap();
+    let out: Output = run_cached_script(input(), &Options::default()).unwrap().into();
+    assert_eq!(out.stdout_output().trim(), nonce.to_string());
+}
@@ -14,12 +14,119 @@ fn test_expr_comma() {
     ).unwrap()
 }
 
+#[test]
+fn test_expr_output_format() {
+    let out = cargo_script!("--output-format", "pretty", "-e", with_output_marker!("(1, 2)")).unwrap();
+    assert_eq!(out.stdout_output().trim(), "(\n    1,\n    2,\n)");
+
+    let out = cargo_script!("--output-format", "display", "-e", with_output_marker!(r#""hi""#)).unwrap();
+    assert_eq!(out.stdout_output().trim(), "hi");
+
+    let out = cargo_script!("--output-format", "json", "-e", with_output_marker!("vec![1, 2, 3]")).unwrap();
+    assert_eq!(out.stdout_output().trim(), "[1,2,3]");
+}
+
 #[test]
 fn test_expr_dnc() {
     let out = cargo_script!("-e", "swing begin").unwrap();
     assert!(!out.success());
 }
 
+#[test]
+fn test_expr_dump_expanded() {
+    let out = cargo_script!("--dump-expanded", "-e", with_output_marker!("1 + 2")).unwrap();
+    assert!(out.stderr.contains("fn try_main()"));
+    assert!(out.stderr.contains("1 + 2}"));
+    scan!(out.stdout_output();
+        ("3") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_expr_edition() {
+    let out = cargo_script!("--edition", "2018", "-e", with_output_marker!("let async = 1; async")).unwrap();
+    assert!(!out.success());
+
+    let out = cargo_script!("--edition", "2021", "-e", with_output_marker!("let x: Box<dyn Fn() -> i32> = Box::new(|| 3); x()")).unwrap();
+    scan!(out.stdout_output();
+        ("3") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_expr_exit_code() {
+    let out = cargo_script!("-e", "::std::process::exit(42)").unwrap();
+    assert_eq!(out.status.code(), Some(42));
+
+    // A script that doesn't build, and one that panics, must be told apart.
+    let out = cargo_script!("-e", "swing begin").unwrap();
+    assert_eq!(out.status.code(), Some(102));
+
+    let out = cargo_script!("-e", "panic!()").unwrap();
+    assert_eq!(out.status.code(), Some(101));
+}
+
+#[test]
+fn test_expr_build_failure_line() {
+    let out = cargo_script!("-e", "swing begin").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("wrapped in a template"));
+}
+
+#[test]
+fn test_expr_timeout() {
+    let out = cargo_script!("--timeout", "1",
+        "-e", "::std::thread::sleep(::std::time::Duration::from_secs(60))").unwrap();
+    assert_eq!(out.status.code(), Some(124));
+    assert!(out.stderr.contains("timed out"));
+
+    let out = cargo_script!("--timeout", "60", "-e", with_output_marker!("1 + 2")).unwrap();
+    scan!(out.stdout_output();
+        ("3") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_expr_lints() {
+    let out = cargo_script!("--deny", "unused_variables", "-e", "{ let x = 1; 2 }").unwrap();
+    assert!(!out.success());
+
+    let out = cargo_script!("--deny", "unused_variables", "--allow", "unused_variables",
+        "-e", with_output_marker!("{{ let x = 1; 2 }}")).unwrap();
+    scan!(out.stdout_output();
+        ("2") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_expr_env() {
+    let out = cargo_script!("--env", "GREETING=a=b",
+        "-e", with_output_marker!(r#"::std::env::var("GREETING") == Ok("a=b".into())"#)).unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_expr_prelude() {
+    let out = cargo_script!(
+        "--expr-prelude", "use std::collections::BTreeMap;",
+        "-e", with_output_marker!("let mut m = BTreeMap::new(); m.insert(1, 2); m")
+    ).unwrap();
+    scan!(out.stdout_output();
+        ("{1: 2}") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_expr_run_dir() {
+    let out = cargo_script!("--run-dir", "tests/data",
+        "-e", with_output_marker!(r#"::std::path::Path::new("script-short.rs").exists()"#)).unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    ).unwrap()
+}
+
 #[test]
 fn test_expr_temporary() {
     let out = cargo_script!("-e", "[1].iter().max()").unwrap();
@@ -38,6 +145,74 @@ fn test_expr_dep() {
     ).unwrap()
 }
 
+#[test]
+fn test_expr_output_marker() {
+    let out = cargo_script!("--output-marker", "--message-format", "json", "-e", "1 + 2").unwrap();
+    assert!(out.success());
+    let begin = out.stdout.find("--cargo-script-output-begin--\n").expect("no begin marker");
+    let end = out.stdout.find("--cargo-script-output-end--\n").expect("no end marker");
+    assert_eq!(out.stdout[begin..end].lines().skip(1).collect::<Vec<_>>(), vec!["3"]);
+}
+
+#[test]
+fn test_expr_exit_expr() {
+    let out = cargo_script!("--exit-expr", "-e", "(1 + 1) as i32 * 21").unwrap();
+    assert_eq!(out.status.code(), Some(42));
+    assert_eq!(out.stdout.trim(), "");
+
+    let out = cargo_script!("--exit-expr", "-e", "\"not a number\"").unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_expr_multiple() {
+    let out = cargo_script!("-e", with_output_marker!("let x = 6"), "-e", "let y = 7;", "-e", "x * y").unwrap();
+    scan!(out.stdout_output();
+        ("42") => ()
+    ).unwrap();
+}
+
+#[test]
+fn test_expr_args() {
+    let out = cargo_script!("-e", with_output_marker!("let n = 1"), "-e",
+        "::std::env::args().skip(n).collect::<Vec<_>>()", "a", "b").unwrap();
+    assert_eq!(out.stdout_output().trim(), r#"["a", "b"]"#);
+
+    // A bare `-e` still takes the expression from `<script>`.
+    let out = cargo_script!("-e", "--output-format", "display", with_output_marker!("6 * 7")).unwrap();
+    assert_eq!(out.stdout_output().trim(), "42");
+}
+
+#[test]
+fn test_expr_prelude_file() {
+    let out = cargo_script!("--prelude-file", "tests/data/prelude.rs",
+        "--expr-prelude", "fn quadruple(x: i32) -> i32 { double(double(x)) }",
+        "-e", with_output_marker!("quadruple(10) + double(1)")).unwrap();
+    scan!(out.stdout_output();
+        ("42") => ()
+    ).unwrap();
+
+    let out = cargo_script!("--prelude-file", "tests/data/does-not-exist.rs", "-e", "1").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("cannot read prelude file"));
+}
+
+#[test]
+fn test_expr_manifest() {
+    let out = cargo_script!("--manifest", r#"dependencies = { boolinator = "0.1.0" }"#,
+        "-e", with_output_marker!(
+            prelude "extern crate boolinator;";
+            "boolinator::Boolinator::as_some(true, 1)"
+        )).unwrap();
+    scan!(out.stdout_output();
+        ("Some(1)") => ()
+    ).unwrap();
+
+    let out = cargo_script!("--manifest", "[dependencies", "-e", "1").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("could not parse `--manifest`"));
+}
+
 #[test]
 fn test_expr_dep_extern() {
     let out = cargo_script!("-d", "boolinator=0.1.0", "-x", "boolinator",
@@ -67,12 +242,56 @@ fn test_expr_dep_extern() {
     assert!(!out.success());
 }
 
+#[test]
+fn test_expr_dep_extern_glob() {
+    let out = cargo_script!("-d", "boolinator=0.1.0", "-X", "boolinator",
+        "-e", with_output_marker!("true.as_some(1)")).unwrap();
+    scan!(out.stdout_output();
+        ("Some(1)") => ()
+    ).unwrap();
+
+    let out = cargo_script!("-d", "boolinator=0.1.0", "-X", "boolinator::*",
+        "-e", with_output_marker!("true")).unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_expr_input() {
+    let out = cargo_script!("--input", "tests/data/script-no-deps.rs",
+        "-e", with_output_marker!("input.contains(\"Hello, World!\")")).unwrap();
+    scan!(out.stdout_output();
+        ("true") => ()
+    ).unwrap();
+
+    let out = cargo_script!("--input", "tests/data/does-not-exist.txt", "-e", "1").unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_expr_stdin_as() {
+    // The tests don't give the script anything on standard input.
+    let out = cargo_script!("--stdin-as", "text", "-e", with_output_marker!("text.len()")).unwrap();
+    scan!(out.stdout_output();
+        ("0") => ()
+    ).unwrap();
+
+    let out = cargo_script!("--stdin-as", "text", "-l", "|l| l.len()").unwrap();
+    assert!(!out.success());
+}
+
 #[test]
 fn test_expr_panic() {
     let out = cargo_script!("-e", with_output_marker!("panic!()")).unwrap();
     assert!(!out.success());
 }
 
+#[test]
+fn test_expr_panic_backtrace() {
+    let out = cargo_script!("--backtrace", "-e", with_output_marker!("panic!()")).unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("stack backtrace:"));
+}
+
 #[test]
 fn test_expr_qmark() {
     let code = if cfg!(has_qmark) {
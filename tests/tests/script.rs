@@ -6,6 +6,75 @@ fn test_script_explicit() {
     ).unwrap()
 }
 
+#[test]
+fn test_script_explain_cache_decision() {
+    extern crate tempdir;
+    let pkg_path = tempdir::TempDir::new("cargo-script-test").unwrap();
+
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "--explain-cache-decision", "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(out.stderr.contains("cache: recompiling because: failed to load metadata"));
+    assert!(out.stderr.contains("cache: verdict: rebuild"));
+
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "--explain-cache-decision", "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(out.stderr.contains("cache: metadata matches cached package"));
+    assert!(out.stderr.contains("cache: verdict: reuse cached executable"));
+}
+
+#[test]
+fn test_script_extract() {
+    // `--extract` can't be used with `--pkg-path`, so this goes through a throwaway cache instead.
+    extern crate tempdir;
+    let cache_dir = tempdir::TempDir::new("cargo-script-test-cache").unwrap();
+    let out_dir = tempdir::TempDir::new("cargo-script-test-extract").unwrap();
+    let dir = out_dir.path().join("extracted");
+
+    let out = cargo_script!(#[cmd("script")] #[env(CARGO_SCRIPT_CACHE_DIR=cache_dir.path())]
+        "--extract", &dir, "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(!out.stdout.contains("Hello, World!"));
+    assert!(dir.join("Cargo.toml").is_file());
+    assert!(dir.join("src/main.rs").is_file());
+
+    let out = cargo_script!(#[cmd("script")] #[env(CARGO_SCRIPT_CACHE_DIR=cache_dir.path())]
+        "--extract", &dir, "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("already exists"));
+}
+
+#[test]
+fn test_script_bin() {
+    let out = cargo_script!("--bin", "second", "tests/data/script-bins.rs").unwrap();
+    scan!(out.stdout_output();
+        ("running second") => ()
+    ).unwrap();
+
+    let out = cargo_script!("tests/data/script-bins.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("use `--bin` to pick which to run: first, second"));
+
+    let out = cargo_script!("--bin", "third", "tests/data/script-bins.rs").unwrap();
+    assert!(!out.success());
+}
+
+#[test]
+fn test_script_crate_type() {
+    // The library is left in the package, so that has to outlive the run.
+    extern crate tempdir;
+    let pkg_path = tempdir::TempDir::new("cargo-script-test").unwrap();
+
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "--crate-type", "cdylib", "tests/data/script-cdylib.rs").unwrap();
+    assert!(out.success());
+    let lib_path = ::std::path::PathBuf::from(out.stdout.trim());
+    assert!(lib_path.is_file(), "{:?}", lib_path);
+    assert_eq!(lib_path.extension().and_then(|e| e.to_str()),
+        Some(::std::env::consts::DLL_EXTENSION));
+}
+
 #[test]
 fn test_script_features() {
     let out = cargo_script!("--features", "dont-panic", "tests/data/script-features.rs").unwrap();
@@ -15,6 +84,26 @@ fn test_script_features() {
 
     let out = cargo_script!("tests/data/script-features.rs").unwrap();
     assert!(!out.success());
+
+    let out = cargo_script!("--all-features", "tests/data/script-features.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Keep calm and borrow check.") => ()
+    ).unwrap();
+
+    let out = cargo_script!("--features", "nope", "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(out.stderr.contains("warning: ignoring feature flags"));
+}
+
+#[test]
+fn test_script_no_main() {
+    let out = cargo_script!("tests/data/script-no-main.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Some(1)") => ()
+    ).unwrap();
+
+    let out = cargo_script!("--file", "tests/data/script-no-main.rs").unwrap();
+    assert!(!out.success());
 }
 
 #[test]
@@ -33,6 +122,25 @@ fn test_script_full_line() {
     ).unwrap()
 }
 
+#[test]
+fn test_script_install() {
+    extern crate tempdir;
+    let bin_dir = tempdir::TempDir::new("cargo-script-test-bin").unwrap();
+    let bin_dir = bin_dir.path().to_str().unwrap();
+    let exe = format!("script-no-deps{}", ::std::env::consts::EXE_SUFFIX);
+
+    let out = cargo_script!("--install", "--install-dir", bin_dir, "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(::std::path::Path::new(bin_dir).join(&exe).is_file());
+
+    let out = cargo_script!("--install", "--install-dir", bin_dir, "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("use --overwrite to replace it"));
+
+    let out = cargo_script!("--install", "--overwrite", "--install-dir", bin_dir, "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+}
+
 #[test]
 fn test_script_invalid_doc_comment() {
     let out = cargo_script!("tests/data/script-invalid-doc-comment.rs").unwrap();
@@ -41,6 +149,15 @@ fn test_script_invalid_doc_comment() {
     ).unwrap()
 }
 
+#[test]
+fn test_script_message_format() {
+    let out = cargo_script!("--message-format", "json", "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.stdout.contains(r#""reason":"compiler-artifact""#));
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    ).unwrap()
+}
+
 #[test]
 fn test_script_no_deps() {
     let out = cargo_script!("tests/data/script-no-deps.rs").unwrap();
@@ -49,6 +166,74 @@ fn test_script_no_deps() {
     ).unwrap()
 }
 
+#[test]
+fn test_script_hooks() {
+    let out = cargo_script!("--pre-build-hook", "echo pre-build-hook-ran",
+        "--post-run-hook", "echo post-run-hook-ran",
+        "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("pre-build-hook-ran"));
+    assert!(out.stdout.contains("post-run-hook-ran"));
+
+    let out = cargo_script!("--pre-build-hook", "exit 2", "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+    assert!(!out.stdout.contains("Hello, World!"));
+}
+
+#[test]
+fn test_script_prime() {
+    extern crate tempdir;
+    let pkg_path = tempdir::TempDir::new("cargo-script-test").unwrap();
+
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "--prime", "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(!out.stdout.contains("Hello, World!"));
+
+    // The stub mustn't be mistaken for the real thing, either by `--run-cached`...
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "--run-cached", "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("there is no up-to-date cached build of this script"));
+
+    // ... or by a normal run, which has to build the script properly.
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "tests/data/script-no-deps.rs").unwrap();
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    ).unwrap()
+}
+
+#[test]
+fn test_script_profile() {
+    let out = cargo_script!("--profile", "checked", "tests/data/script-profile.rs").unwrap();
+    scan!(out.stdout_output();
+        ("debug assertions: true") => ()
+    ).unwrap();
+
+    let out = cargo_script!("tests/data/script-profile.rs").unwrap();
+    scan!(out.stdout_output();
+        ("debug assertions: false") => ()
+    ).unwrap();
+}
+
+#[test]
+fn test_script_print_binary_path() {
+    let out = cargo_script!("--print-binary-path", "tests/data/script-no-deps.rs").unwrap();
+    assert!(out.success());
+    assert!(!out.stdout.contains("Hello, World!"));
+    assert!(::std::path::Path::new(out.stdout.trim()).is_absolute());
+}
+
+#[test]
+fn test_script_quiet() {
+    let out = cargo_script!("--quiet", "tests/data/script-no-deps.rs").unwrap();
+    assert!(!out.stderr_raw().contains("Compiling"));
+    scan!(out.stdout_output();
+        ("Hello, World!") => ()
+    ).unwrap()
+}
+
 #[test]
 fn test_script_short() {
     let out = cargo_script!("tests/data/script-short.rs").unwrap();
@@ -59,8 +244,20 @@ fn test_script_short() {
 
 #[test]
 fn test_script_test() {
+    // There's no `main`, but that doesn't make it an expression.
     let out = cargo_script!("--test", "tests/data/script-test.rs").unwrap();
     assert!(out.success());
+    assert!(out.stdout.contains("test test ... ok"));
+}
+
+#[test]
+fn test_script_test_args() {
+    let out = cargo_script!("--test", "tests/data/script-tests.rs", "--", "passes").unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("test passes ... ok"));
+
+    let out = cargo_script!("--test", "tests/data/script-tests.rs").unwrap();
+    assert!(!out.success());
 }
 
 #[test]
@@ -74,6 +271,32 @@ fn test_script_hyphens() {
     ).unwrap()
 }
 
+#[test]
+fn test_script_run_cached() {
+    extern crate tempdir;
+    use scan_rules::scanner::QuotedString;
+    let pkg_path = tempdir::TempDir::new("cargo-script-test").unwrap();
+
+    // Nothing has been built yet, so there's nothing to run.
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "--run-cached", "tests/data/script-args.rs").unwrap();
+    assert!(!out.success());
+    assert!(out.stderr.contains("there is no up-to-date cached build of this script"));
+
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "--no-run", "tests/data/script-args.rs").unwrap();
+    assert!(out.success());
+    assert!(!out.stdout.contains("--output--"));
+
+    let out = cargo_script!(#[pkg_path(pkg_path.path())]
+        "--run-cached", "tests/data/script-args.rs", "cached").unwrap();
+    scan!(out.stdout_output();
+        ("[0]:", let _: QuotedString, "[1]:", let arg: QuotedString) => {
+            assert_eq!(arg, "cached");
+        }
+    ).unwrap();
+}
+
 #[test]
 fn test_script_has_weird_chars() {
     let out = cargo_script!("tests/data/script-has.weird§chars!.rs").unwrap();
@@ -5,4 +5,23 @@ fn test_version() {
     scan!(&out.stdout;
         ("cargo-script", &::std::env::var("CARGO_PKG_VERSION").unwrap(), .._) => ()
     ).unwrap();
+    assert!(out.stdout.contains("\nbuilt with: "));
+    assert!(out.stdout.contains("\nscripts use: "));
+    assert!(out.stdout.contains("\ncache dir: "));
+    assert!(out.stdout.contains("\nconfig dir: "));
+}
+
+#[test]
+fn test_help_usage() {
+    let out = cargo_script!("--help").unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("cargo script [FLAGS OPTIONS] [--] <script> <args>..."));
+}
+
+#[test]
+fn test_doctor() {
+    let out = cargo_script!(#[cmd("doctor")]).unwrap();
+    assert!(out.success());
+    assert!(out.stdout.contains("[  ok] cargo: "));
+    assert!(out.stdout.contains("[  ok] rustc: "));
 }
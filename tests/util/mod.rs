@@ -1,24 +1,27 @@
 use std::sync::Mutex;
 
+// Runs `cargo-script script` with the given arguments, building the script in a fresh package
+// directory.  Use `#[pkg_path(DIR)]` to build in `DIR` instead, so that several runs can share
+// one package, or `#[cmd(ARGS...)]` to replace `script --pkg-path DIR` with `ARGS` entirely.
 macro_rules! cargo_script {
     (
+        #[cmd($($cmd_args:expr),* $(,)*)]
         #[env($($env_k:ident=$env_v:expr),* $(,)*)]
         $($args:expr),* $(,)*
     ) => {
         {
-            extern crate tempdir;
             use std::process::Command;
 
             let cargo_lock = ::util::CARGO_MUTEX.lock().expect("could not acquire Cargo mutext");
 
-            let temp_dir = tempdir::TempDir::new("cargo-script-test").unwrap();
             let cmd_str;
             let out = {
                 let target_dir = ::std::env::var("CARGO_TARGET_DIR")
                     .unwrap_or_else(|_| String::from("target"));
                 let mut cmd = Command::new(format!("{}/debug/cargo-script", target_dir));
-                cmd.arg("script");
-                cmd.arg("--pkg-path").arg(temp_dir.path());
+                $(
+                    cmd.arg($cmd_args);
+                )*
                 $(
                     cmd.arg($args);
                 )*
@@ -44,13 +47,47 @@ macro_rules! cargo_script {
                 println!("-----");
             }
 
-            drop(temp_dir);
             drop(cargo_lock);
 
             out
         }
     };
 
+    (
+        #[cmd($($cmd_args:expr),* $(,)*)]
+        $($args:expr),* $(,)*
+    ) => {
+        cargo_script!(#[cmd($($cmd_args),*)] #[env()] $($args),*)
+    };
+
+    (
+        #[pkg_path($pkg_path:expr)]
+        #[env($($env_k:ident=$env_v:expr),* $(,)*)]
+        $($args:expr),* $(,)*
+    ) => {
+        cargo_script!(#[cmd("script", "--pkg-path", $pkg_path)] #[env($($env_k=$env_v),*)] $($args),*)
+    };
+
+    (
+        #[pkg_path($pkg_path:expr)]
+        $($args:expr),* $(,)*
+    ) => {
+        cargo_script!(#[pkg_path($pkg_path)] #[env()] $($args),*)
+    };
+
+    (
+        #[env($($env_k:ident=$env_v:expr),* $(,)*)]
+        $($args:expr),* $(,)*
+    ) => {
+        {
+            extern crate tempdir;
+            let temp_dir = tempdir::TempDir::new("cargo-script-test").unwrap();
+            let out = cargo_script!(#[pkg_path(temp_dir.path())] #[env($($env_k=$env_v),*)] $($args),*);
+            drop(temp_dir);
+            out
+        }
+    };
+
     ($($args:expr),* $(,)*) => {
         cargo_script!(#[env()] $($args),*)
     };